@@ -0,0 +1,137 @@
+// Incoming MIDI controllers, smoothed so the stepped 7 bit values don't zipper. Updated from the
+// note events in `Synth::process` and read by every voice, one value per sample.
+
+/// One controller, ramping linearly to the last received value.
+#[derive(Default)]
+pub struct SmoothedController {
+    value: f32,
+    target: f32,
+    step: f32, // Change per sample, 0 when the ramp is done
+}
+
+impl SmoothedController {
+    /// The value `samples` further along the ramp, without moving along it
+    pub fn value_after(&self, samples: usize) -> f32 {
+        let value = self.value + self.step * samples as f32;
+        if self.step > 0.0 {
+            value.min(self.target)
+        } else if self.step < 0.0 {
+            value.max(self.target)
+        } else {
+            value
+        }
+    }
+
+    /// Ramp to `target` over `time` seconds. Times under a sample jump straight there.
+    pub fn set_target(&mut self, target: f32, time: f32, sample_rate: f32) {
+        self.target = target;
+        let samples = time * sample_rate;
+        if samples < 1.0 {
+            self.reset(target);
+        } else {
+            self.step = (target - self.value) / samples;
+        }
+    }
+
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+        self.step = 0.0;
+    }
+
+    fn advance(&mut self, samples: usize) {
+        if self.step == 0.0 {
+            return;
+        }
+        self.value += self.step * samples as f32;
+        let overshot = if self.step > 0.0 {
+            self.value >= self.target
+        } else {
+            self.value <= self.target
+        };
+        if overshot {
+            self.reset(self.target);
+        }
+    }
+}
+
+/// The position of every controller at one sample. Mod wheel, pressure and sustain are passed to
+/// the voices for modulation routings to pick up, none read them yet.
+#[allow(dead_code)]
+#[derive(Default, Clone, Copy)]
+pub struct ControllerValues {
+    pub pitch_bend: f32,
+    pub mod_wheel: f32,
+    pub channel_pressure: f32,
+    pub sustain: f32,
+}
+
+/// Pitch bend (-1..1), mod wheel, channel pressure and sustain pedal (0..1).
+#[derive(Default)]
+pub struct Controllers {
+    pub pitch_bend: SmoothedController,
+    pub mod_wheel: SmoothedController,
+    pub channel_pressure: SmoothedController,
+    pub sustain: SmoothedController,
+}
+
+impl Controllers {
+    /// Move every controller `samples` further along its ramp.
+    pub fn advance(&mut self, samples: usize) {
+        for controller in [
+            &mut self.pitch_bend,
+            &mut self.mod_wheel,
+            &mut self.channel_pressure,
+            &mut self.sustain,
+        ] {
+            controller.advance(samples);
+        }
+    }
+
+    /// Fill `block` with the controller positions from now, one per sample, without moving along
+    /// the ramps. Every voice reads the same ramps, `advance` moves them once the block is done.
+    pub fn fill(&self, block: &mut [ControllerValues]) {
+        for (i, values) in block.iter_mut().enumerate() {
+            *values = ControllerValues {
+                pitch_bend: self.pitch_bend.value_after(i),
+                mod_wheel: self.mod_wheel.value_after(i),
+                channel_pressure: self.channel_pressure.value_after(i),
+                sustain: self.sustain.value_after(i),
+            };
+        }
+    }
+}
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller_ramps_to_target_and_stops() {
+        let mut controller = SmoothedController::default();
+        controller.set_target(1.0, 0.01, 1000.0); // 10 samples
+        controller.advance(5);
+        assert!((controller.value_after(0) - 0.5).abs() < 1e-6);
+        controller.advance(100);
+        assert_eq!(controller.value_after(0), 1.0);
+
+        controller.set_target(0.25, 0.0, 1000.0);
+        assert_eq!(controller.value_after(0), 0.25);
+    }
+
+    #[test]
+    fn fill_follows_the_ramps_that_advance_takes() {
+        let mut controllers = Controllers::default();
+        controllers.mod_wheel.set_target(1.0, 0.01, 1000.0); // 10 samples
+        controllers.pitch_bend.set_target(-1.0, 0.02, 1000.0);
+        let mut block = [ControllerValues::default(); 16];
+        controllers.fill(&mut block);
+        assert!((block[4].mod_wheel - 0.4).abs() < 1e-6);
+        assert!((block[4].pitch_bend + 0.2).abs() < 1e-6);
+        assert_eq!(block[15].mod_wheel, 1.0);
+        assert_eq!(block[15].sustain, 0.0);
+
+        controllers.advance(4);
+        assert!((controllers.mod_wheel.value_after(0) - block[4].mod_wheel).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,59 @@
+use nih_plug::prelude::{ParamSetter, Params};
+use serde::{Deserialize, Serialize};
+
+use super::param_snapshot;
+
+/// One parameter moved by the brightness macro. `scale` is the change in normalized value over the
+/// full travel of the macro, negative to move the parameter the other way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroTarget {
+    pub id: String,
+    pub scale: f32,
+}
+
+/// Single knob opening up the sound, saved with the plugin state. Turning it nudges every target
+/// by its share of the movement rather than setting it, so direct edits to the targets are kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrightnessMacro {
+    pub targets: Vec<MacroTarget>,
+    pub position: f32,
+}
+
+impl Default for BrightnessMacro {
+    fn default() -> Self {
+        BrightnessMacro {
+            targets: vec![
+                MacroTarget {
+                    id: "FilterCutoff".to_owned(),
+                    scale: 0.5,
+                },
+                MacroTarget {
+                    id: "FilterEnvModGain".to_owned(),
+                    scale: 0.25,
+                },
+            ],
+            position: 0.5,
+        }
+    }
+}
+
+impl BrightnessMacro {
+    /// Move the macro and nudge every target by the change. Ids that don't match a parameter are
+    /// ignored.
+    pub fn set_position(&mut self, setter: &ParamSetter, params: &impl Params, position: f32) {
+        let position = position.clamp(0.0, 1.0);
+        let delta = position - self.position;
+        self.position = position;
+        if delta == 0.0 {
+            return;
+        }
+        let param_map = params.param_map();
+        for target in &self.targets {
+            if let Some((_, ptr, _)) = param_map.iter().find(|(id, _, _)| *id == target.id) {
+                let value = unsafe { ptr.unmodulated_normalized_value() };
+                let nudged = (value + delta * target.scale).clamp(0.0, 1.0);
+                param_snapshot::set_normalized(setter, *ptr, nudged);
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::VecDeque;
+
+const MAX_EDITS: usize = 16;
+
+pub struct Edit {
+    pub param: String,
+    pub text: String, // As shown on the LCD
+    pub time: u64,    // Milliseconds since the epoch
+}
+
+/// Recent parameter edits, for the history panel. Repeated edits of the same parameter, like the
+/// frames of one knob drag, update its latest entry instead of adding new ones.
+#[derive(Default)]
+pub struct EditHistory {
+    edits: VecDeque<Edit>,
+    pub visible: bool,
+}
+
+impl EditHistory {
+    pub fn push(&mut self, param: &str, text: String, time: u64) {
+        if let Some(last) = self.edits.back_mut().filter(|last| last.param == param) {
+            last.text = text;
+            last.time = time;
+            return;
+        }
+        if self.edits.len() == MAX_EDITS {
+            self.edits.pop_front();
+        }
+        self.edits.push_back(Edit {
+            param: param.to_owned(),
+            text,
+            time,
+        });
+    }
+
+    /// Most recent first
+    pub fn edits(&self) -> impl Iterator<Item = &Edit> {
+        self.edits.iter().rev()
+    }
+}
@@ -0,0 +1,84 @@
+use nih_plug_egui::egui::*;
+use std::ops::RangeInclusive;
+
+const ACTIVE_COLOR: Color32 = Color32::from_rgb(215, 173, 29);
+const BLACK_KEY_HEIGHT: f32 = 0.6; // Relative to the white keys
+
+/// Read only keyboard strip that highlights the notes in a 128 bit mask, one bit per MIDI note.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct Keyboard {
+    active_notes: u128,
+    notes: RangeInclusive<u8>,
+    height: f32,
+}
+
+impl Keyboard {
+    pub fn new(active_notes: u128, notes: RangeInclusive<u8>) -> Self {
+        Self {
+            active_notes,
+            notes,
+            height: 24.0,
+        }
+    }
+
+    pub fn height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+
+    fn is_active(&self, note: u8) -> bool {
+        self.active_notes & (1u128 << note) != 0
+    }
+}
+
+fn is_black_key(note: u8) -> bool {
+    matches!(note % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+impl Widget for Keyboard {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let desired_size = vec2(ui.available_width(), self.height);
+        let (rect, response) = ui.allocate_exact_size(desired_size, Sense::hover());
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+            let white_keys = self.notes.clone().filter(|n| !is_black_key(*n)).count();
+            let white_width = rect.width() / white_keys.max(1) as f32;
+            let outline = Stroke::new(1.0, Color32::from_gray(60));
+
+            // White keys first, so the black keys are drawn on top of them
+            let mut x = rect.left();
+            let mut black_keys = Vec::new();
+            for note in self.notes.clone() {
+                if is_black_key(note) {
+                    black_keys.push((note, x));
+                    continue;
+                }
+                let key =
+                    Rect::from_min_size(pos2(x, rect.top()), vec2(white_width, rect.height()));
+                let fill = if self.is_active(note) {
+                    ACTIVE_COLOR
+                } else {
+                    Color32::from_gray(220)
+                };
+                painter.rect(key, 0.0, fill, outline);
+                x += white_width;
+            }
+            let black_width = white_width * 0.6;
+            for (note, x) in black_keys {
+                let key = Rect::from_min_size(
+                    pos2(x - black_width * 0.5, rect.top()),
+                    vec2(black_width, rect.height() * BLACK_KEY_HEIGHT),
+                );
+                let fill = if self.is_active(note) {
+                    ACTIVE_COLOR
+                } else {
+                    Color32::from_gray(30)
+                };
+                painter.rect(key, 0.0, fill, outline);
+            }
+        }
+
+        response
+    }
+}
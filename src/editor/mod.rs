@@ -1,7 +1,16 @@
 mod audio_slider;
+pub mod brightness_macro;
+pub mod edit_history;
 pub mod frame_history;
+mod keyboard;
+pub mod param_clipboard;
+pub mod param_snapshot;
+mod waveform_display;
+pub mod xy_macro;
+mod xy_pad;
 use crate::oscillator::WaveForm;
 use crate::*;
+use atomic_float::AtomicF32;
 use egui_extras::{Size, StripBuilder};
 use egui_extras_xt::common::WidgetShape;
 use egui_extras_xt::displays::{DisplayStylePreset, IndicatorButton, SegmentedDisplayWidget};
@@ -10,11 +19,13 @@ use nih_plug_egui::egui::{
     self, CentralPanel, Color32, FontData, FontDefinitions, FontFamily, Label, RichText, Ui,
     WidgetText,
 };
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
 const WINDOW_WIDTH: u32 = 562;
-const WINDOW_HEIGHT: u32 = 488;
+const WINDOW_HEIGHT: u32 = 548;
 const SHOW_FPS: bool = false;
+const KEYBOARD_NOTES: std::ops::RangeInclusive<u8> = 24..=108; // C1 to C8
 
 pub fn default_editor_state() -> Arc<EguiState> {
     EguiState::from_size(WINDOW_WIDTH, WINDOW_HEIGHT)
@@ -22,7 +33,31 @@ pub fn default_editor_state() -> Arc<EguiState> {
 
 pub struct SynthUiState {
     pub edit_text: Mutex<EditText>,
+    pub edit_history: Mutex<edit_history::EditHistory>,
     pub frame_history: Mutex<frame_history::FrameHistory>,
+    pub correlation: AtomicF32, // Output L/R phase correlation, -1..1, written by the audio thread
+    pub active_voices: AtomicU32, // Number of playing voices, written by the audio thread
+    pub ab_compare: Mutex<param_snapshot::AbCompare>,
+    pub preset_bank: Mutex<SynthPresetBank>,
+    pub preset_index: AtomicU32, // Last selected preset in the bank
+    /// The bank's presets resolved against the plugin's parameters, for program changes to load on
+    /// the audio thread. Kept up to date with the bank by the editor.
+    pub program_snapshots: Mutex<Vec<param_snapshot::ResolvedSnapshot>>,
+    pub active_notes: [AtomicU64; 2], // Bitmask of sounding notes, written by the audio thread
+    pub param_clipboard: Mutex<param_clipboard::ParamClipboard>,
+    pub osc_levels: [AtomicF32; 2], // OSC1/OSC2 peak level over all voices, written by the audio thread
+}
+
+impl SynthUiState {
+    pub fn active_notes(&self) -> u128 {
+        self.active_notes[0].load(Ordering::Relaxed) as u128
+            | (self.active_notes[1].load(Ordering::Relaxed) as u128) << 64
+    }
+
+    pub fn set_active_notes(&self, notes: u128) {
+        self.active_notes[0].store(notes as u64, Ordering::Relaxed);
+        self.active_notes[1].store((notes >> 64) as u64, Ordering::Relaxed);
+    }
 }
 
 pub fn create_editor(
@@ -53,18 +88,39 @@ pub fn create_editor(
             let mut fps_history = ui_state.frame_history.lock().unwrap();
             fps_history.on_new_frame(time);
 
+            // Shift+Z is redo elsewhere, so don't undo another step for it
+            let modifiers = egui_ctx.input().modifiers;
+            if modifiers.command && !modifiers.shift && egui_ctx.input().key_pressed(egui::Key::Z) {
+                ui_state.param_clipboard.lock().unwrap().undo(setter);
+            }
+
+            // Pasting a patch string anywhere in the editor loads it
+            let pasted_patch = egui_ctx
+                .input()
+                .events
+                .iter()
+                .find_map(|event| match event {
+                    egui::Event::Paste(text) => presets::SynthPreset::from_patch_string(text),
+                    _ => None,
+                });
+            if let Some(preset) = pasted_patch {
+                let snapshot = preset.to_snapshot(params.as_ref());
+                param_snapshot::apply_snapshot(setter, params.as_ref(), &snapshot);
+            }
+
             CentralPanel::default().show(egui_ctx, |ui| {
                 ui.spacing_mut().item_spacing = egui::vec2(12.0, 12.0);
                 ui.style_mut().spacing.slider_width = 64.0;
                 StripBuilder::new(ui)
                     .size(Size::exact(54.0)) // top bar
                     .size(Size::remainder()) // control section
+                    .size(Size::exact(24.0)) // keyboard
                     .size(Size::exact(if SHOW_FPS { 10.0 } else { 0.0 })) // footer
                     .vertical(|mut strip| {
                         // Top bar              
                         strip.strip(|builder| {
                             reset_edit_text(&ui_state);
-                            builder.size(Size::remainder()).size(Size::exact(48.0)).horizontal(|mut strip| {
+                            builder.size(Size::remainder()).size(Size::exact(120.0)).horizontal(|mut strip| {
                                 strip.cell(|ui| {
                                     ui.vertical(|ui| {
 
@@ -74,36 +130,6 @@ pub fn create_editor(
                                             _ => "",
                                         };
 
-                                        /*  TODO: Presets not implemented in VST3 / nih-plug version
-                                        let (preset_index, preset_name) = (0, "".to_owned()); {
-                                            let preset_index = state.ui_state.preset_index;
-                                            let bank = state.preset_bank.lock().unwrap();
-                                            let s = (*bank).presets[preset_index as usize].name.to_string();
-                                            (preset_index, s)
-                                        };
-                                        // Top lcd (preset display)
-                                        ui.spacing_mut().item_spacing = egui::vec2(4.0, 4.0);
-                                        let preset_text = format!("{:>2}: {}", preset_index, preset_name);
-                                        ui.horizontal(|ui| {
-                                            ui.add(
-                                                SegmentedDisplayWidget::sixteen_segment(lcd_format(&preset_text, 25))
-                                                    .style_preset(DisplayStylePreset::DeLoreanAmber)
-                                                    .show_dots(true)
-                                                    .show_colons(true)
-                                                    .show_apostrophes(false)
-                                                    .digit_height(20.0),
-                                            );
-                                            if ui.button("<").clicked() {
-                                                //state.change_preset(preset_index - 1);
-                                            }
-                                            if ui.button(">").clicked() {
-                                                //state.change_preset(preset_index + 1);
-                                            }
-                                            if ui.button("Write").clicked() {
-                                                //state.write_current_preset();
-                                            }
-                                        });
-                                         */
                                         // Bottom lcd
                                         ui.add(
                                             SegmentedDisplayWidget::sixteen_segment(lcd_format(&action_txt, 30))
@@ -113,325 +139,432 @@ pub fn create_editor(
                                                 .show_apostrophes(false)
                                                 .digit_height(20.0),
                                         );
+                                        ui.horizontal(|ui| {
+                                            let correlation = ui_state.correlation.load(Ordering::Relaxed);
+                                            ui.label(RichText::new(format!("Corr {:+.2}", correlation)).size(10.0).color(correlation_color(correlation)));
+                                            let active_voices = ui_state.active_voices.load(Ordering::Relaxed);
+                                            ui.label(RichText::new(format!("Voices: {}/{}", active_voices, NUM_VOICES)).size(10.0));
+
+                                            let mut ab = ui_state.ab_compare.lock().unwrap();
+                                            if ui.small_button("Store A").clicked() {
+                                                ab.store(0, params.as_ref());
+                                            }
+                                            if ui.small_button("Store B").clicked() {
+                                                ab.store(1, params.as_ref());
+                                            }
+                                            let ab_label = if ab.active() == 0 { "A/B: A" } else { "A/B: B" };
+                                            if ui
+                                                .add_enabled(ab.is_stored(0) && ab.is_stored(1), egui::Button::new(ab_label).small())
+                                                .clicked()
+                                            {
+                                                ab.toggle(setter, params.as_ref());
+                                            }
+
+                                            let performance_view = params.performance_view.load(Ordering::Relaxed);
+                                            if ui.selectable_label(performance_view, "Perform").clicked() {
+                                                params.performance_view.store(!performance_view, Ordering::Relaxed);
+                                            }
+                                            let mut history = ui_state.edit_history.lock().unwrap();
+                                            if ui.selectable_label(history.visible, "History").clicked() {
+                                                history.visible = !history.visible;
+                                            }
+                                            drop(history);
+
+                                            // Preset bank, kept in memory
+                                            let mut bank = ui_state.preset_bank.lock().unwrap();
+                                            let count = bank.presets.len() as u32;
+                                            let preset_index = ui_state.preset_index.load(Ordering::Relaxed);
+                                            let mut change_to = None;
+                                            if ui.small_button("<").clicked() && count > 0 {
+                                                change_to = Some((preset_index + count - 1) % count);
+                                            }
+                                            if let Some(preset) = bank.presets.get(preset_index as usize) {
+                                                ui.label(RichText::new(format!("{:>2}: {}", preset_index, preset.name)).size(10.0));
+                                            }
+                                            if ui.small_button(">").clicked() && count > 0 {
+                                                change_to = Some((preset_index + 1) % count);
+                                            }
+                                            if ui.small_button("Write").clicked() {
+                                                if let Some(preset) = bank.presets.get_mut(preset_index as usize) {
+                                                    *preset = presets::SynthPreset::from_params(preset.name.clone(), params.as_ref());
+                                                    let snapshot = param_snapshot::resolve_snapshot(params.as_ref(), &preset.to_snapshot(params.as_ref()));
+                                                    if let Some(program) = ui_state.program_snapshots.lock().unwrap().get_mut(preset_index as usize) {
+                                                        *program = snapshot;
+                                                    }
+                                                }
+                                            }
+                                            if ui
+                                                .small_button("Copy")
+                                                .on_hover_text("Copy the patch as text. Paste a copied patch with Ctrl+V.")
+                                                .clicked()
+                                            {
+                                                let preset = presets::SynthPreset::from_params("Shared", params.as_ref());
+                                                ui.output().copied_text = preset.to_patch_string();
+                                            }
+                                            if let Some(index) = change_to {
+                                                let snapshot = bank.presets[index as usize].to_snapshot(params.as_ref());
+                                                param_snapshot::apply_snapshot(setter, params.as_ref(), &snapshot);
+                                                ui_state.preset_index.store(index, Ordering::Relaxed);
+                                            }
+                                        });
                                     }); // 2 lcds
                                 });
                                 strip.cell(|ui| {
                                     ui.spacing_mut().item_spacing = egui::vec2(0.0, 0.0);
-                                    create_param_knob("Master", ui, setter, &params.master_gain, &ui_state, true, false);
+                                    StripBuilder::new(ui)
+                                        .size(Size::relative(1.0 / 3.0))
+                                        .size(Size::relative(1.0 / 3.0))
+                                        .size(Size::relative(1.0 / 3.0))
+                                        .horizontal(|mut strip| {
+                                            strip.cell(|ui| {
+                                                create_param_knob("Tune", ui, setter, &params.master_tuning, &ui_state, true, true);
+                                            });
+                                            strip.cell(|ui| {
+                                                create_param_knob("Pan", ui, setter, &params.master_pan, &ui_state, true, true);
+                                            });
+                                            strip.cell(|ui| {
+                                                create_param_knob("Master", ui, setter, &params.master_gain, &ui_state, true, false);
+                                            });
+                                        });
                                 });
                             });
                         });
                         // Main control section
-                        strip.strip(|builder| {
-                            builder
-                                .size(Size::exact(70.0)) // LFO  column
-                                .size(Size::exact(70.0)) // OSC1 column
-                                .size(Size::exact(70.0)) // OSC2 column
-                                .size(Size::exact(144.0)) // MIX/UNISON column
-                                .size(Size::exact(144.0)) // FILTER column
-                                .horizontal(|mut strip| {
-                                    // LFO column
-                                    strip.cell(|ui| {
-                                        control_block("LFO", ui, |ui| {
-                                            ui.horizontal(|ui| {
-                                                waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Sine);
-                                                waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Triangle);
-                                                waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Square);
-                                            });
-                                            ui.vertical_centered(|ui| {
-                                                let host_sync = params.lfo_host_sync.value();
-                                                ui.add_space(8.0);
-                                                ui.add(
-                                                    IndicatorButton::from_get_set(|new_val: Option<bool>| {
-                                                        if let Some(v) = new_val {
-                                                            setter.set_parameter(&params.lfo_host_sync, v);
-                                                            set_edit_param(&ui_state, &params.lfo_host_sync);
-                                                            v
-                                                        } else {
-                                                            host_sync
-                                                        }
-                                                    })
-                                                    .label("Sync")
-                                                    .style(DisplayStylePreset::DeLoreanAmber.style())
-                                                    .height(32.0)
-                                                    .width(48.0),
-                                                );
-                                                ui.add(
-                                                    IndicatorButton::from_get_set(|new_val: Option<bool>| {
-                                                        if let Some(v) = new_val {
-                                                            setter.set_parameter(&params.lfo_key_trig, v);
-                                                            set_edit_param(&ui_state, &params.lfo_key_trig);
-                                                            v
-                                                        } else {
-                                                            params.lfo_key_trig.value()
-                                                        }
-                                                    })
-                                                    .label("Retrig")
-                                                    .style(DisplayStylePreset::DeLoreanAmber.style())
-                                                    .interactive(!host_sync)
-                                                    .height(32.0)
-                                                    .width(48.0),
-                                                );
-                                                create_param_knob(
-                                                    "Rate",
-                                                    ui,
-                                                    setter,
-                                                    &params.lfo_freq,
-                                                    &ui_state,
-                                                    !params.lfo_host_sync.value(),
-                                                    false,
-                                                );
-                                                ui.add(
-                                                    IndicatorButton::from_get_set(|new_val: Option<bool>| {
-                                                        if let Some(v) = new_val {
-                                                            setter.set_parameter(&params.poly_mode, v);
-                                                            v
-                                                        } else {
-                                                            params.poly_mode.value()
-                                                        }
-                                                    })
-                                                    .label("Poly")
-                                                    .style(DisplayStylePreset::DeLoreanAmber.style())
-                                                    .height(32.0)
-                                                    .width(48.0),
-                                                );
-                                                create_param_knob(
-                                                    "Porta",
-                                                    ui,
-                                                    setter,
-                                                    &params.portamento,
-                                                    &ui_state,
-                                                    !params.poly_mode.value(),
-                                                    false,
-                                                );
-                                            });
-                                        });
-                                    }); // End LFO column
-
-                                    // OSC1 column
-                                    strip.cell(|ui| {
-                                        control_block("OSC1", ui, |ui| {
-                                            ui.vertical_centered(|ui| {
+                        if params.performance_view.load(Ordering::Relaxed) {
+                            strip.cell(|ui| {
+                                performance_view(ui, setter, &params, &ui_state);
+                            });
+                        } else {
+                            strip.strip(|builder| {
+                                builder
+                                    .size(Size::exact(70.0)) // LFO  column
+                                    .size(Size::exact(70.0)) // OSC1 column
+                                    .size(Size::exact(70.0)) // OSC2 column
+                                    .size(Size::exact(144.0)) // MIX/UNISON column
+                                    .size(Size::exact(144.0)) // FILTER column
+                                    .horizontal(|mut strip| {
+                                        // LFO column
+                                        strip.cell(|ui| {
+                                            control_block("LFO", ui, |ui| {
                                                 ui.horizontal(|ui| {
-                                                    waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Saw); 
-                                                    waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Square);
-                                                    waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Sine);
+                                                    waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Sine);
+                                                    waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Triangle);
+                                                    waveform_button(ui, setter, &params.lfo_waveform, LfoWaveFormParameter::Square);
                                                 });
-                                                create_param_knob("Oct", ui, setter, &params.osc1_octave, &ui_state, true, true);
-                                                create_param_knob("Detune", ui, setter, &params.osc1_detune, &ui_state, true, true);
-                                                param_knob("PW", ui, setter, &params.osc1_pulsewidth, &ui_state);
-                                                create_param_knob("LFO", ui, setter, &params.lfo_osc1_detune_mod_depth, &ui_state, true, true);
-                                            });
-                                        });
-                                    }); // End OSC1 column
-
-                                    // OSC2 column
-                                    strip.cell(|ui| {
-                                        control_block("OSC2", ui, |ui| {
-                                            ui.vertical_centered(|ui| {
-                                                ui.horizontal(|ui| {
-                                                    waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Saw);
-                                                    waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Square);
-                                                    waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Sine);
+                                                ui.vertical_centered(|ui| {
+                                                    let host_sync = params.lfo_host_sync.value();
+                                                    ui.add_space(8.0);
+                                                    ui.add(
+                                                        IndicatorButton::from_get_set(|new_val: Option<bool>| {
+                                                            if let Some(v) = new_val {
+                                                                setter.set_parameter(&params.lfo_host_sync, v);
+                                                                set_edit_param(&ui_state, &params.lfo_host_sync);
+                                                                v
+                                                            } else {
+                                                                host_sync
+                                                            }
+                                                        })
+                                                        .label("Sync")
+                                                        .style(DisplayStylePreset::DeLoreanAmber.style())
+                                                        .height(32.0)
+                                                        .width(48.0),
+                                                    );
+                                                    ui.add(
+                                                        IndicatorButton::from_get_set(|new_val: Option<bool>| {
+                                                            if let Some(v) = new_val {
+                                                                setter.set_parameter(&params.lfo_key_trig, v);
+                                                                set_edit_param(&ui_state, &params.lfo_key_trig);
+                                                                v
+                                                            } else {
+                                                                params.lfo_key_trig.value()
+                                                            }
+                                                        })
+                                                        .label("Retrig")
+                                                        .style(DisplayStylePreset::DeLoreanAmber.style())
+                                                        .interactive(!host_sync)
+                                                        .height(32.0)
+                                                        .width(48.0),
+                                                    );
+                                                    if params.lfo_host_sync.value() {
+                                                        create_param_knob("Rate", ui, setter, &params.lfo_sync_division, &ui_state, true, false);
+                                                    } else {
+                                                        create_param_knob("Rate", ui, setter, &params.lfo_freq, &ui_state, true, false);
+                                                    }
+                                                    ui.add(
+                                                        IndicatorButton::from_get_set(|new_val: Option<bool>| {
+                                                            if let Some(v) = new_val {
+                                                                setter.set_parameter(&params.poly_mode, v);
+                                                                v
+                                                            } else {
+                                                                params.poly_mode.value()
+                                                            }
+                                                        })
+                                                        .label("Poly")
+                                                        .style(DisplayStylePreset::DeLoreanAmber.style())
+                                                        .height(32.0)
+                                                        .width(48.0),
+                                                    );
+                                                    create_param_knob(
+                                                        "Porta",
+                                                        ui,
+                                                        setter,
+                                                        &params.portamento,
+                                                        &ui_state,
+                                                        !params.poly_mode.value(),
+                                                        false,
+                                                    );
                                                 });
-                                                create_param_knob("Oct", ui, setter, &params.osc2_octave, &ui_state, true, true);
-                                                create_param_knob("Detune", ui, setter, &params.osc2_detune, &ui_state, true, true);
-                                                param_knob("PW", ui, setter, &params.osc2_pulsewidth, &ui_state);
                                             });
-                                        });
-                                    }); // End OSC2 column
+                                        }); // End LFO column
 
-                                    // MIX/UNISON column
-                                    strip.strip(|builder| {
-                                        builder.size(Size::exact(96.0)).size(Size::remainder()).size(Size::exact(144.0)).vertical(
-                                            |mut strip| {
-                                                // Row 1/3: Mix
-                                                strip.cell(|ui| {
-                                                    control_block("MIX", ui, |ui| {
-                                                        ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
-                                                        StripBuilder::new(ui)
-                                                            .size(Size::relative(0.5))
-                                                            .size(Size::relative(0.5))
-                                                            .horizontal(|mut strip| {
-                                                                // OscLevel - Osc2Level
-                                                                strip.cell(|ui| {
-                                                                    param_knob("Osc 1", ui, setter, &params.osc1_level, &ui_state);
-                                                                });
-                                                                strip.cell(|ui| {
-                                                                    param_knob("Osc 2", ui, setter, &params.osc2_level, &ui_state);
-                                                                });
-                                                            }); // End levels side by side
+                                        // OSC1 column
+                                        strip.cell(|ui| {
+                                            control_block("OSC1", ui, |ui| {
+                                                ui.vertical_centered(|ui| {
+                                                    ui.horizontal(|ui| {
+                                                        waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Saw); 
+                                                        waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Square);
+                                                        waveform_button(ui, setter, &params.osc1_waveform, WaveFormParameter::Sine);
                                                     });
+                                                    ui.add(waveform_display::WaveformDisplay::new(
+                                                        params.osc1_waveform.value().into(),
+                                                        params.osc1_pulsewidth.value(),
+                                                    ));
+                                                    ui.horizontal(|ui| {
+                                                        bool_param_button("Mute", ui, setter, &params.osc1_on, false, &ui_state);
+                                                        bool_param_button("Solo", ui, setter, &params.osc1_solo, true, &ui_state);
+                                                    });
+                                                    create_param_knob("Oct", ui, setter, &params.osc1_octave, &ui_state, true, true);
+                                                    create_param_knob("Detune", ui, setter, &params.osc1_detune, &ui_state, true, true);
+                                                    param_knob("PW", ui, setter, &params.osc1_pulsewidth, &ui_state);
+                                                    create_param_knob("LFO", ui, setter, &params.lfo_osc1_detune_mod_depth, &ui_state, true, true);
                                                 });
+                                            });
+                                        }); // End OSC1 column
 
-                                                // Row 2/3: Unison
-                                                strip.cell(|ui| {
-                                                    control_block("UNISON", ui, |ui| {
-                                                        ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
-                                                        ui.vertical_centered(|ui| {
-                                                            StripBuilder::new(ui)
-                                                                .size(Size::exact(24.0))
-                                                                .size(Size::remainder())
-                                                                .vertical(|mut strip| {
-                                                                    strip.cell(|ui| {
-                                                                        let num_buttons = params.unison_voices.step_count().unwrap() + 1;
-                                                                        let nvoices = params.unison_voices.value();
-                                                                        const MIN: i32 = 1;
-                                                                        const MAX: i32 = 7; // TODO how to get range from IntParam?
-                                                                        StripBuilder::new(ui)
-                                                                            .sizes(
-                                                                                Size::relative(1.0 / num_buttons as f32),
-                                                                                num_buttons,
-                                                                            )
-                                                                            .horizontal(|mut strip| {
-                                                                                for n in MIN..=MAX {
-                                                                                    strip.cell(|ui| {
-                                                                                        if ui
-                                                                                            .selectable_label(
-                                                                                                nvoices == n,
-                                                                                                format!("{}", n),
-                                                                                            )
-                                                                                            .clicked()
-                                                                                        {
-                                                                                            setter.set_parameter(&params.unison_voices, n);
-                                                                                            set_edit_param(&ui_state, &params.unison_voices);
-                                                                                        }
-                                                                                    });
-                                                                                }
-                                                                            });
-                                                                    });
-                                                                    strip.cell(|ui| {
-                                                                        ui.add_space(4.0);
-                                                                        StripBuilder::new(ui)
-                                                                            .size(Size::relative(0.5))
-                                                                            .size(Size::relative(0.5))
-                                                                            .horizontal(|mut strip| {
-                                                                                strip.cell(|ui| {
-                                                                                    param_knob("Spread", ui, setter, &params.unison_stereo_spread, &ui_state);
-                                                                                });
-                                                                                strip.cell(|ui| {
-                                                                                    param_knob("Detune", ui, setter, &params.unison_detune, &ui_state);
-                                                                                });
-                                                                            });
-                                                                    });
-                                                                });
-                                                        });
+                                        // OSC2 column
+                                        strip.cell(|ui| {
+                                            control_block("OSC2", ui, |ui| {
+                                                ui.vertical_centered(|ui| {
+                                                    ui.horizontal(|ui| {
+                                                        waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Saw);
+                                                        waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Square);
+                                                        waveform_button(ui, setter, &params.osc2_waveform, WaveFormParameter::Sine);
                                                     });
+                                                    ui.add(waveform_display::WaveformDisplay::new(
+                                                        params.osc2_waveform.value().into(),
+                                                        params.osc2_pulsewidth.value(),
+                                                    ));
+                                                    ui.horizontal(|ui| {
+                                                        bool_param_button("Mute", ui, setter, &params.osc2_on, false, &ui_state);
+                                                        bool_param_button("Solo", ui, setter, &params.osc2_solo, true, &ui_state);
+                                                    });
+                                                    create_param_knob("Oct", ui, setter, &params.osc2_octave, &ui_state, true, true);
+                                                    create_param_knob("Detune", ui, setter, &params.osc2_detune, &ui_state, true, true);
+                                                    param_knob("PW", ui, setter, &params.osc2_pulsewidth, &ui_state);
+                                                    param_knob("Sync", ui, setter, &params.osc_sync, &ui_state);
+                                                    create_param_knob("LFO", ui, setter, &params.lfo_osc2_detune_mod_depth, &ui_state, true, true);
                                                 });
+                                            });
+                                        }); // End OSC2 column
 
-                                                // Row 3/3 Amp env
-                                                strip.cell(|ui| {
-                                                    control_block("AMP ENV", ui, |ui| {
-                                                        ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
-                                                        ui.vertical_centered(|ui| {
+                                        // MIX/UNISON column
+                                        strip.strip(|builder| {
+                                            builder.size(Size::exact(96.0)).size(Size::remainder()).size(Size::exact(144.0)).vertical(
+                                                |mut strip| {
+                                                    // Row 1/3: Mix
+                                                    strip.cell(|ui| {
+                                                        control_block("MIX", ui, |ui| {
+                                                            ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
                                                             StripBuilder::new(ui)
-                                                                .size(Size::relative(0.25))
-                                                                .size(Size::relative(0.25))
-                                                                .size(Size::relative(0.25))
-                                                                .size(Size::relative(0.25))
+                                                                .size(Size::relative(0.5))
+                                                                .size(Size::relative(0.5))
                                                                 .horizontal(|mut strip| {
+                                                                    // OscLevel - Osc2Level
                                                                     strip.cell(|ui| {
-                                                                        param_slider("A", ui, setter, &params.amp_env_attack, &ui_state);
-                                                                    });
-                                                                    strip.cell(|ui| {
-                                                                        param_slider("D", ui, setter, &params.amp_env_decay, &ui_state);
-                                                                    });
-                                                                    strip.cell(|ui| {
-                                                                        param_slider("S", ui, setter, &params.amp_env_sustain, &ui_state);
+                                                                        param_knob("Osc 1", ui, setter, &params.osc1_level, &ui_state);
+                                                                        level_meter(ui, ui_state.osc_levels[0].load(Ordering::Relaxed));
                                                                     });
                                                                     strip.cell(|ui| {
-                                                                        param_slider("R", ui, setter, &params.amp_env_release, &ui_state);
+                                                                        param_knob("Osc 2", ui, setter, &params.osc2_level, &ui_state);
+                                                                        level_meter(ui, ui_state.osc_levels[1].load(Ordering::Relaxed));
                                                                     });
-                                                                });
+                                                                }); // End levels side by side
                                                         });
                                                     });
-                                                });
-                                            },
-                                        ); // End unison row
-                                    }); // End MIX/UNISON column
 
-                                    // FILTER column
-                                    strip.strip(|builder| {
-                                        builder.size(Size::remainder()).size(Size::exact(144.0)).vertical(|mut strip| {
-                                            strip.cell(|ui| {
-                                                control_block("FILTER", ui, |ui| {
-                                                    ui.spacing_mut().item_spacing = egui::vec2(0.0, 2.0);
-                                                    StripBuilder::new(ui)
-                                                        .size(Size::exact(64.0))
-                                                        .size(Size::exact(64.0))
-                                                        .size(Size::exact(64.0))
-                                                        .vertical(|mut strip| {
-                                                            strip.cell(|ui| {
+                                                    // Row 2/3: Unison
+                                                    strip.cell(|ui| {
+                                                        control_block("UNISON", ui, |ui| {
+                                                            ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
+                                                            ui.vertical_centered(|ui| {
                                                                 StripBuilder::new(ui)
-                                                                    .size(Size::relative(0.5))
-                                                                    .size(Size::relative(0.5))
-                                                                    .horizontal(|mut strip| {
+                                                                    .size(Size::exact(24.0))
+                                                                    .size(Size::remainder())
+                                                                    .vertical(|mut strip| {
                                                                         strip.cell(|ui| {
-                                                                            param_knob("Cutoff", ui, setter, &params.filter_cutoff, &ui_state);
+                                                                            let num_buttons = params.unison_voices.step_count().unwrap() + 1;
+                                                                            let nvoices = params.unison_voices.value();
+                                                                            const MIN: i32 = 1;
+                                                                            const MAX: i32 = 7; // TODO how to get range from IntParam?
+                                                                            StripBuilder::new(ui)
+                                                                                .sizes(
+                                                                                    Size::relative(1.0 / num_buttons as f32),
+                                                                                    num_buttons,
+                                                                                )
+                                                                                .horizontal(|mut strip| {
+                                                                                    for n in MIN..=MAX {
+                                                                                        strip.cell(|ui| {
+                                                                                            if ui
+                                                                                                .selectable_label(
+                                                                                                    nvoices == n,
+                                                                                                    format!("{}", n),
+                                                                                                )
+                                                                                                .clicked()
+                                                                                            {
+                                                                                                setter.set_parameter(&params.unison_voices, n);
+                                                                                                set_edit_param(&ui_state, &params.unison_voices);
+                                                                                            }
+                                                                                        });
+                                                                                    }
+                                                                                });
                                                                         });
                                                                         strip.cell(|ui| {
-                                                                            param_knob("Res", ui, setter, &params.filter_resonance, &ui_state);
+                                                                            ui.add_space(4.0);
+                                                                            StripBuilder::new(ui)
+                                                                                .size(Size::relative(0.5))
+                                                                                .size(Size::relative(0.5))
+                                                                                .horizontal(|mut strip| {
+                                                                                    strip.cell(|ui| {
+                                                                                        param_knob("Spread", ui, setter, &params.unison_stereo_spread, &ui_state);
+                                                                                    });
+                                                                                    strip.cell(|ui| {
+                                                                                        param_knob("Detune", ui, setter, &params.unison_detune, &ui_state);
+                                                                                    });
+                                                                                });
                                                                         });
-                                                                    }); // End cutoff/resonance
+                                                                    });
                                                             });
-                                                            strip.cell(|ui| {
+                                                        });
+                                                    });
+
+                                                    // Row 3/3 Amp env
+                                                    strip.cell(|ui| {
+                                                        control_block("AMP ENV", ui, |ui| {
+                                                            ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
+                                                            ui.vertical_centered(|ui| {
                                                                 StripBuilder::new(ui)
-                                                                    .size(Size::relative(0.5))
-                                                                    .size(Size::relative(0.5))
+                                                                    .size(Size::relative(0.25))
+                                                                    .size(Size::relative(0.25))
+                                                                    .size(Size::relative(0.25))
+                                                                    .size(Size::relative(0.25))
                                                                     .horizontal(|mut strip| {
                                                                         strip.cell(|ui| {
-                                                                            create_param_knob("Env", ui, setter, &params.filter_env_mod_gain, &ui_state, true, true);
+                                                                            param_slider("A", ui, setter, &params.amp_env_attack, &ui_state);
                                                                         });
                                                                         strip.cell(|ui| {
-                                                                            param_knob("Key", ui, setter, &params.filter_key_track, &ui_state);
+                                                                            param_slider("D", ui, setter, &params.amp_env_decay, &ui_state);
                                                                         });
-                                                                    }); // End envmod/keytrack
-                                                            });
-                                                            strip.cell(|ui| {
-                                                                StripBuilder::new(ui)
-                                                                    .size(Size::relative(0.5))
-                                                                    .size(Size::relative(0.5))
-                                                                    .horizontal(|mut strip| {
                                                                         strip.cell(|ui| {
-                                                                            create_param_knob("LFO", ui, setter, &params.lfo_filter_mod_depth, &ui_state, true, true);
+                                                                            param_slider("S", ui, setter, &params.amp_env_sustain, &ui_state);
                                                                         });
                                                                         strip.cell(|ui| {
-                                                                            param_knob("Vel", ui, setter, &params.filter_velocity_mod, &ui_state);
+                                                                            param_slider("R", ui, setter, &params.amp_env_release, &ui_state);
                                                                         });
-                                                                    }); // End filter lfo mod/velocity mod
+                                                                    });
                                                             });
                                                         });
-                                                });
-                                            });
-                                            strip.cell(|ui| {
-                                                control_block("FILTER ENV", ui, |ui| {
-                                                    ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
-                                                    ui.vertical_centered(|ui| {
+                                                    });
+                                                },
+                                            ); // End unison row
+                                        }); // End MIX/UNISON column
+
+                                        // FILTER column
+                                        strip.strip(|builder| {
+                                            builder.size(Size::remainder()).size(Size::exact(144.0)).vertical(|mut strip| {
+                                                strip.cell(|ui| {
+                                                    control_block("FILTER", ui, |ui| {
+                                                        ui.spacing_mut().item_spacing = egui::vec2(0.0, 2.0);
                                                         StripBuilder::new(ui)
-                                                            .size(Size::relative(0.25))
-                                                            .size(Size::relative(0.25))
-                                                            .size(Size::relative(0.25))
-                                                            .size(Size::relative(0.25))
-                                                            .horizontal(|mut strip| {
-                                                                strip.cell(|ui| {
-                                                                    param_slider("A", ui, setter, &params.filter_env_attack, &ui_state);
-                                                                });
+                                                            .size(Size::exact(64.0))
+                                                            .size(Size::exact(64.0))
+                                                            .size(Size::exact(64.0))
+                                                            .vertical(|mut strip| {
                                                                 strip.cell(|ui| {
-                                                                    param_slider("D", ui, setter, &params.filter_env_decay, &ui_state);
+                                                                    StripBuilder::new(ui)
+                                                                        .size(Size::relative(0.5))
+                                                                        .size(Size::relative(0.5))
+                                                                        .horizontal(|mut strip| {
+                                                                            strip.cell(|ui| {
+                                                                                param_knob("Cutoff", ui, setter, &params.filter_cutoff, &ui_state);
+                                                                            });
+                                                                            strip.cell(|ui| {
+                                                                                param_knob("Res", ui, setter, &params.filter_resonance, &ui_state);
+                                                                            });
+                                                                        }); // End cutoff/resonance
                                                                 });
                                                                 strip.cell(|ui| {
-                                                                    param_slider("S", ui, setter, &params.filter_env_sustain, &ui_state);
+                                                                    StripBuilder::new(ui)
+                                                                        .size(Size::relative(0.5))
+                                                                        .size(Size::relative(0.5))
+                                                                        .horizontal(|mut strip| {
+                                                                            strip.cell(|ui| {
+                                                                                create_param_knob("Env", ui, setter, &params.filter_env_mod_gain, &ui_state, true, true);
+                                                                            });
+                                                                            strip.cell(|ui| {
+                                                                                param_knob("Key", ui, setter, &params.filter_key_track, &ui_state);
+                                                                            });
+                                                                        }); // End envmod/keytrack
                                                                 });
                                                                 strip.cell(|ui| {
-                                                                    param_slider("R", ui, setter, &params.filter_env_release, &ui_state);
+                                                                    StripBuilder::new(ui)
+                                                                        .size(Size::relative(0.5))
+                                                                        .size(Size::relative(0.5))
+                                                                        .horizontal(|mut strip| {
+                                                                            strip.cell(|ui| {
+                                                                                create_param_knob("LFO", ui, setter, &params.lfo_filter_mod_depth, &ui_state, true, true);
+                                                                            });
+                                                                            strip.cell(|ui| {
+                                                                                param_knob("Vel", ui, setter, &params.filter_velocity_mod, &ui_state);
+                                                                            });
+                                                                        }); // End filter lfo mod/velocity mod
                                                                 });
                                                             });
                                                     });
                                                 });
+                                                strip.cell(|ui| {
+                                                    control_block("FILTER ENV", ui, |ui| {
+                                                        ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
+                                                        ui.vertical_centered(|ui| {
+                                                            StripBuilder::new(ui)
+                                                                .size(Size::relative(0.25))
+                                                                .size(Size::relative(0.25))
+                                                                .size(Size::relative(0.25))
+                                                                .size(Size::relative(0.25))
+                                                                .horizontal(|mut strip| {
+                                                                    strip.cell(|ui| {
+                                                                        param_slider("A", ui, setter, &params.filter_env_attack, &ui_state);
+                                                                    });
+                                                                    strip.cell(|ui| {
+                                                                        param_slider("D", ui, setter, &params.filter_env_decay, &ui_state);
+                                                                    });
+                                                                    strip.cell(|ui| {
+                                                                        param_slider("S", ui, setter, &params.filter_env_sustain, &ui_state);
+                                                                    });
+                                                                    strip.cell(|ui| {
+                                                                        param_slider("R", ui, setter, &params.filter_env_release, &ui_state);
+                                                                    });
+                                                                });
+                                                        });
+                                                    });
+                                                });
                                             });
-                                        });
-                                    }); // End FILTER column
-                                }); // End main columns
+                                        }); // End FILTER column
+                                    }); // End main columns
+                            });
+                        }
+                        strip.cell(|ui| {
+                            ui.add(keyboard::Keyboard::new(ui_state.active_notes(), KEYBOARD_NOTES));
                         });
                         if SHOW_FPS {
                             strip.cell(|ui|{
@@ -440,6 +573,32 @@ pub fn create_editor(
                         }
                     }); // End vertical display/main
             });
+
+            let mut history = ui_state.edit_history.lock().unwrap();
+            let mut visible = history.visible;
+            egui::Window::new("Edit History")
+                .open(&mut visible)
+                .collapsible(false)
+                .resizable(false)
+                .show(egui_ctx, |ui| {
+                    let now = now();
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            if history.edits().next().is_none() {
+                                ui.label(RichText::new("Nothing edited yet").size(10.0));
+                            }
+                            for edit in history.edits() {
+                                let age = now.saturating_sub(edit.time) / 1000;
+                                ui.label(
+                                    RichText::new(format!("{:>4}s ago  {}", age, edit.text))
+                                        .size(10.0)
+                                        .monospace(),
+                                );
+                            }
+                        });
+                });
+            history.visible = visible;
         },
     )
 }
@@ -470,8 +629,14 @@ where
         param.name(),
         param.normalized_value_to_string(param.preview_normalized(plain), true)
     );
+    let time = now();
+    ui_state
+        .edit_history
+        .lock()
+        .unwrap()
+        .push(param.name(), s.clone(), time);
     let mut txt = ui_state.edit_text.lock().unwrap();
-    *txt = EditText::Editing(s, now());
+    *txt = EditText::Editing(s, time);
 }
 
 fn control_block(header: &str, ui: &mut Ui, controls: impl FnOnce(&mut Ui)) {
@@ -501,6 +666,161 @@ fn control_block(header: &str, ui: &mut Ui, controls: impl FnOnce(&mut Ui)) {
         });
 }
 
+/// Simplified layout for live use: a few large controls and an XY pad
+fn performance_view(
+    ui: &mut Ui,
+    setter: &ParamSetter,
+    params: &Arc<SynthParams>,
+    ui_state: &Arc<SynthUiState>,
+) {
+    StripBuilder::new(ui)
+        .size(Size::exact(144.0))
+        .size(Size::remainder())
+        .horizontal(|mut strip| {
+            strip.cell(|ui| {
+                control_block("PERFORM", ui, |ui| {
+                    large_param_knob("Cutoff", ui, setter, &params.filter_cutoff, ui_state);
+                    large_param_knob("Resonance", ui, setter, &params.filter_resonance, ui_state);
+                    brightness_knob(ui, setter, params);
+                    large_param_knob("Master", ui, setter, &params.master_gain, ui_state);
+                });
+            });
+            strip.cell(|ui| {
+                control_block("XY", ui, |ui| {
+                    xy_pad_controls(ui, setter, params);
+                });
+            });
+        });
+}
+
+/// Brightness macro knob, nudging the filter cutoff and envelope amount together
+fn brightness_knob(ui: &mut Ui, setter: &ParamSetter, params: &Arc<SynthParams>) {
+    let mut brightness = params.brightness_macro.lock().unwrap();
+    ui.vertical_centered(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(0.0, 0.0);
+        let mut position = brightness.position;
+        let response = ui.add(
+            AudioKnob::from_get_set(|new_val: Option<f32>| {
+                if let Some(v) = new_val {
+                    position = v;
+                }
+                position
+            })
+            .diameter(64.0)
+            .range(0.0..=1.0)
+            .shape(WidgetShape::Circle)
+            .thickness(0.3)
+            .spread(0.8)
+            .animated(true),
+        );
+        if response.changed() {
+            brightness.set_position(setter, params.as_ref(), position);
+        }
+        ui.add_space(8.0);
+        ui.add(Label::new("Brightness"));
+    });
+}
+
+/// XY macro pad. Each axis drives any number of parameters, each over its own range.
+fn xy_pad_controls(ui: &mut Ui, setter: &ParamSetter, params: &Arc<SynthParams>) {
+    let param_map = params.param_map();
+    let mut xy_macro = params.xy_macro.lock().unwrap();
+
+    ui.vertical_centered(|ui| {
+        let rows = (xy_macro.x.len() + xy_macro.y.len() + 2) as f32;
+        let size = egui::vec2(
+            ui.available_width() - 16.0,
+            (ui.available_height() - 8.0 - rows * 22.0).max(64.0),
+        );
+        let mut position = xy_macro.position;
+        let response = ui.add(
+            xy_pad::XyPad::from_get_set(|new_val: Option<(f32, f32)>| {
+                if let Some(new_position) = new_val {
+                    position = new_position;
+                }
+                position
+            })
+            .size(size),
+        );
+        if response.changed() {
+            xy_macro.set_position(setter, params.as_ref(), position);
+        }
+        ui.add_space(8.0);
+
+        let assignments = &mut *xy_macro;
+        for (label, targets) in [("X", &mut assignments.x), ("Y", &mut assignments.y)] {
+            let mut remove = None;
+            for (i, target) in targets.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(label);
+                    let selected = param_map
+                        .iter()
+                        .find(|(id, _, _)| *id == target.id)
+                        .map(|(_, ptr, _)| unsafe { ptr.name() }.to_string())
+                        .unwrap_or_default();
+                    egui::ComboBox::from_id_source(("xy-target", label, i))
+                        .selected_text(selected)
+                        .width(size.x - 150.0)
+                        .show_ui(ui, |ui| {
+                            for (id, ptr, _) in param_map.iter() {
+                                ui.selectable_value(&mut target.id, id.clone(), unsafe {
+                                    ptr.name()
+                                });
+                            }
+                        });
+                    ui.add(
+                        egui::DragValue::new(&mut target.min)
+                            .clamp_range(0.0..=1.0)
+                            .speed(0.01),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut target.max)
+                            .clamp_range(0.0..=1.0)
+                            .speed(0.01),
+                    );
+                    if ui.small_button("x").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                targets.remove(i);
+            }
+            if ui.small_button(format!("+ {}", label)).clicked() {
+                targets.push(xy_macro::XyTarget::new("FilterCutoff"));
+            }
+        }
+    });
+}
+
+/// Small horizontal peak meter showing -60..0 dB, red when over 0 dB
+fn level_meter(ui: &mut Ui, level: f32) {
+    ui.vertical_centered(|ui| {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(32.0, 3.0), egui::Sense::hover());
+        let db = util::gain_to_db(level.max(1e-6));
+        let mut lit = rect;
+        lit.set_width(rect.width() * ((db + 60.0) / 60.0).clamp(0.0, 1.0));
+        let color = if db > 0.0 {
+            Color32::RED
+        } else {
+            Color32::from_rgb(215, 173, 29)
+        };
+        let painter = ui.painter();
+        painter.rect_filled(rect, 0.0, Color32::from_gray(40));
+        painter.rect_filled(lit, 0.0, color);
+    });
+}
+
+fn correlation_color(correlation: f32) -> Color32 {
+    if correlation < 0.0 {
+        Color32::RED
+    } else if correlation < 0.5 {
+        Color32::YELLOW
+    } else {
+        Color32::GREEN
+    }
+}
+
 fn lcd_format(msg: &str, width: usize) -> String {
     let mut result = String::new();
     let mut len = 0;
@@ -524,16 +844,16 @@ fn waveform_button<E>(ui: &mut Ui, setter: &ParamSetter, param: &EnumParam<E>, b
 where
     E: Copy + Enum + PartialEq + Into<WaveForm> + 'static,
 {
-    let osc_wf: WaveForm = param.value().into();
     let osc_btn_wf: WaveForm = btn_waveform.into();
     let symbol = match osc_btn_wf {
         WaveForm::Saw => '\u{2a58}',
-        WaveForm::Square | WaveForm::UnipolarSquare => '\u{2293}',
+        WaveForm::Square => '\u{2293}',
+        WaveForm::UnipolarSquare => '\u{238d}',
         WaveForm::Sine => '\u{223f}',
-        WaveForm::Triangle => '\u{2227}',
+        WaveForm::Triangle => '\u{25b3}',
     };
     let label = egui::SelectableLabel::new(
-        osc_wf == osc_btn_wf,
+        param.value() == btn_waveform,
         egui::RichText::new(format!("{}", symbol)).monospace(),
     );
     let response = ui.add(label);
@@ -542,6 +862,32 @@ where
     }
 }
 
+/// Small indicator button for a bool parameter, lit while the parameter is `lit_value`
+fn bool_param_button(
+    label: &str,
+    ui: &mut Ui,
+    setter: &ParamSetter,
+    param: &BoolParam,
+    lit_value: bool,
+    ui_state: &Arc<SynthUiState>,
+) {
+    ui.add(
+        IndicatorButton::from_get_set(|new_val: Option<bool>| {
+            if let Some(lit) = new_val {
+                setter.set_parameter(param, lit == lit_value);
+                set_edit_param(ui_state, param);
+                lit
+            } else {
+                param.value() == lit_value
+            }
+        })
+        .label(label)
+        .style(DisplayStylePreset::DeLoreanAmber.style())
+        .height(16.0)
+        .width(28.0),
+    );
+}
+
 fn param_knob<P>(
     label: impl Into<WidgetText>,
     ui: &mut Ui,
@@ -554,6 +900,18 @@ fn param_knob<P>(
     create_param_knob(label, ui, setter, param, ui_state, true, false);
 }
 
+fn large_param_knob<P>(
+    label: impl Into<WidgetText>,
+    ui: &mut Ui,
+    setter: &ParamSetter,
+    param: &P,
+    ui_state: &Arc<SynthUiState>,
+) where
+    P: Param,
+{
+    create_param_knob_with_diameter(label, ui, setter, param, ui_state, true, false, 64.0);
+}
+
 fn create_param_knob<P>(
     label: impl Into<WidgetText>,
     ui: &mut Ui,
@@ -564,28 +922,67 @@ fn create_param_knob<P>(
     symmetric: bool,
 ) where
     P: Param,
+{
+    create_param_knob_with_diameter(
+        label,
+        ui,
+        setter,
+        param,
+        ui_state,
+        interactive,
+        symmetric,
+        32.0,
+    );
+}
+
+// Symmetric knobs run from -0.5 to 0.5 so their arc starts at the centre of the parameter range
+fn knob_to_normalized(knob: f32, symmetric: bool) -> f32 {
+    if symmetric {
+        knob + 0.5
+    } else {
+        knob
+    }
+}
+
+fn normalized_to_knob(normalized: f32, symmetric: bool) -> f32 {
+    if symmetric {
+        normalized - 0.5
+    } else {
+        normalized
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_param_knob_with_diameter<P>(
+    label: impl Into<WidgetText>,
+    ui: &mut Ui,
+    setter: &ParamSetter,
+    param: &P,
+    ui_state: &Arc<SynthUiState>,
+    interactive: bool,
+    symmetric: bool,
+    diameter: f32,
+) where
+    P: Param,
 {
     ui.vertical_centered(|ui| {
         let knob_range = if symmetric { -0.5..=0.5 } else { 0.0..=1.0 };
-        let offset = if symmetric { -0.5 } else { 0.0 }; // Offset between normalized value and knob value.
         ui.spacing_mut().item_spacing = egui::vec2(0.0, 0.0);
 
         let knob = AudioKnob::from_get_set(|new_val: Option<f32>| {
             if let Some(k) = new_val {
-                let v = k - offset;
-                setter.set_parameter_normalized(param, v);
+                setter.set_parameter_normalized(param, knob_to_normalized(k, symmetric));
                 set_edit_param(ui_state, param);
-                v
+                k
             } else {
                 if interactive {
-                    let normalized_value = param.unmodulated_normalized_value();
-                    normalized_value + offset
+                    normalized_to_knob(param.unmodulated_normalized_value(), symmetric)
                 } else {
                     0.0
                 }
             }
         })
-        .diameter(32.0)
+        .diameter(diameter)
         //.drag_length(3.0)
         .range(knob_range)
         .shape(WidgetShape::Circle)
@@ -595,18 +992,22 @@ fn create_param_knob<P>(
         .animated(true);
 
         // Snap
+        let before = param.unmodulated_normalized_value();
         let response = ui.add(knob);
         ui.add_space(8.0);
         ui.add(Label::new(label));
 
         if response.double_clicked() {
+            push_undo(ui_state, param, before);
             setter.set_parameter(param, param.default_plain_value());
         }
         if response.drag_started() {
+            push_undo(ui_state, param, before);
             setter.begin_set_parameter(param);
         } else if response.drag_released() {
             setter.end_set_parameter(param);
         }
+        param_context_menu(response, setter, param, ui_state);
     });
 }
 
@@ -634,15 +1035,55 @@ fn param_slider<P>(
         )
         .text(label);
         ui.add_space(10.0);
+        let before = param.unmodulated_normalized_value();
         let response = ui.add(slider);
         if response.double_clicked() {
+            push_undo(ui_state, param, before);
             setter.set_parameter(param, param.default_plain_value());
         }
         if response.drag_started() {
+            push_undo(ui_state, param, before);
             setter.begin_set_parameter(param);
         } else if response.drag_released() {
             setter.end_set_parameter(param);
         }
+        param_context_menu(response, setter, param, ui_state);
+    });
+}
+
+fn push_undo<P>(ui_state: &Arc<SynthUiState>, param: &P, normalized_before: f32)
+where
+    P: Param,
+{
+    let mut clipboard = ui_state.param_clipboard.lock().unwrap();
+    clipboard.push_undo(param.as_ptr(), normalized_before);
+}
+
+/// Right click menu to copy the value of a parameter and paste it onto another one
+fn param_context_menu<P>(
+    response: egui::Response,
+    setter: &ParamSetter,
+    param: &P,
+    ui_state: &Arc<SynthUiState>,
+) where
+    P: Param,
+{
+    response.context_menu(|ui| {
+        if ui.button("Copy").clicked() {
+            ui_state.param_clipboard.lock().unwrap().copy(param);
+            ui.close_menu();
+        }
+        let paste_value = ui_state.param_clipboard.lock().unwrap().paste_value(param);
+        if ui
+            .add_enabled(paste_value.is_some(), egui::Button::new("Paste"))
+            .clicked()
+        {
+            if let Some(value) = paste_value {
+                push_undo(ui_state, param, param.unmodulated_normalized_value());
+                param_snapshot::set_normalized(setter, param.as_ptr(), value);
+            }
+            ui.close_menu();
+        }
     });
 }
 
@@ -651,3 +1092,33 @@ fn wrapper(ui: &Ui, color: Color32) {
     ui.painter()
         .rect_filled(ui.available_rect_before_wrap(), 0.0, color);
 }
+
+#[allow(unused)]
+mod tests {
+    use super::{knob_to_normalized, normalized_to_knob};
+    use crate::symmetric_percentage_param;
+    use assert_approx_eq::assert_approx_eq;
+    use nih_plug::prelude::*;
+
+    #[test]
+    fn symmetric_knob_turns_equally_far_either_side_of_the_centre() {
+        let param = symmetric_percentage_param("Filter env mod");
+        assert_eq!(param.preview_plain(knob_to_normalized(0.0, true)), 0.0);
+        for knob in [0.05, 0.25, 0.5] {
+            let up = param.preview_plain(knob_to_normalized(knob, true));
+            let down = param.preview_plain(knob_to_normalized(-knob, true));
+            assert!(up > 0.0);
+            assert_approx_eq!(up, -down, 1e-6);
+            assert_approx_eq!(
+                normalized_to_knob(param.preview_normalized(up), true),
+                knob,
+                1e-6
+            );
+            assert_approx_eq!(
+                normalized_to_knob(param.preview_normalized(down), true),
+                -knob,
+                1e-6
+            );
+        }
+    }
+}
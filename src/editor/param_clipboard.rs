@@ -0,0 +1,42 @@
+use nih_plug::prelude::{Param, ParamPtr, ParamSetter};
+
+use super::param_snapshot;
+
+const MAX_UNDO: usize = 64;
+
+/// Copied parameter value and the undo stack for edits made in the editor.
+#[derive(Default)]
+pub struct ParamClipboard {
+    /// Normalized value, and the step count of the parameter it was copied from
+    copied: Option<(f32, Option<usize>)>,
+    /// Value of each edited parameter before the edit, most recent last
+    undo: Vec<(ParamPtr, f32)>,
+}
+
+impl ParamClipboard {
+    pub fn copy<P: Param>(&mut self, param: &P) {
+        self.copied = Some((param.unmodulated_normalized_value(), param.step_count()));
+    }
+
+    /// The copied value, if there is one and it was copied from a parameter with the same steps.
+    pub fn paste_value<P: Param>(&self, param: &P) -> Option<f32> {
+        self.copied
+            .filter(|(_, step_count)| *step_count == param.step_count())
+            .map(|(value, _)| value)
+    }
+
+    /// Remember the value of a parameter that is about to change.
+    pub fn push_undo(&mut self, ptr: ParamPtr, normalized_before: f32) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push((ptr, normalized_before));
+    }
+
+    /// Restore the parameter changed by the last edit.
+    pub fn undo(&mut self, setter: &ParamSetter) {
+        if let Some((ptr, value)) = self.undo.pop() {
+            param_snapshot::set_normalized(setter, ptr, value);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use nih_plug::prelude::{ParamPtr, ParamSetter, Params};
+
+/// Normalized value of every parameter, by parameter id
+pub type ParamSnapshot = Vec<(String, f32)>;
+
+pub fn take_snapshot(params: &impl Params) -> ParamSnapshot {
+    params
+        .param_map()
+        .into_iter()
+        .map(|(id, ptr, _)| (id, unsafe { ptr.unmodulated_normalized_value() }))
+        .collect()
+}
+
+/// Apply a snapshot through the host, so that the change is recorded as automation. Ids that don't
+/// match a parameter are ignored.
+pub fn apply_snapshot(setter: &ParamSetter, params: &impl Params, snapshot: &ParamSnapshot) {
+    let param_map = params.param_map();
+    for (id, value) in snapshot {
+        if let Some((_, ptr, _)) = param_map.iter().find(|(param_id, _, _)| param_id == id) {
+            set_normalized(setter, *ptr, *value);
+        }
+    }
+}
+
+/// A snapshot with its parameters already looked up, so that it can be applied on the audio thread
+/// without allocating. Only valid for as long as the parameters it was resolved from.
+pub type ResolvedSnapshot = Vec<(ParamPtr, f32)>;
+
+pub fn resolve_snapshot(params: &impl Params, snapshot: &ParamSnapshot) -> ResolvedSnapshot {
+    let param_map = params.param_map();
+    snapshot
+        .iter()
+        .filter_map(|(id, value)| {
+            let (_, ptr, _) = param_map.iter().find(|(param_id, _, _)| param_id == id)?;
+            Some((*ptr, *value))
+        })
+        .collect()
+}
+
+/// Set the parameters directly, for the audio thread where there is no setter. The host isn't
+/// told about the change, and smoothed parameters glide to their new values.
+///
+/// # Safety
+///
+/// The parameters the snapshot was resolved from must still be alive, and this must be called from
+/// the audio thread.
+pub unsafe fn apply_resolved_snapshot(snapshot: &ResolvedSnapshot, sample_rate: f32) {
+    for (ptr, value) in snapshot {
+        ptr.set_normalized_value(*value);
+        ptr.update_smoother(sample_rate, false);
+    }
+}
+
+/// Set a single parameter as one complete automation gesture.
+pub fn set_normalized(setter: &ParamSetter, ptr: ParamPtr, value: f32) {
+    unsafe {
+        setter.raw_context.raw_begin_set_parameter(ptr);
+        setter.raw_context.raw_set_parameter_normalized(ptr, value);
+        setter.raw_context.raw_end_set_parameter(ptr);
+    }
+}
+
+/// Two in-memory parameter snapshots to compare while editing. Not saved with the plugin state.
+#[derive(Default)]
+pub struct AbCompare {
+    slots: [Option<ParamSnapshot>; 2],
+    active: usize,
+}
+
+impl AbCompare {
+    pub fn store(&mut self, slot: usize, params: &impl Params) {
+        self.slots[slot] = Some(take_snapshot(params));
+        self.active = slot;
+    }
+
+    pub fn is_stored(&self, slot: usize) -> bool {
+        self.slots[slot].is_some()
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Switch to the other slot, if it has been stored.
+    pub fn toggle(&mut self, setter: &ParamSetter, params: &impl Params) {
+        let other = 1 - self.active;
+        if let Some(snapshot) = &self.slots[other] {
+            apply_snapshot(setter, params, snapshot);
+            self.active = other;
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::oscillator::WaveForm;
+use nih_plug_egui::egui::*;
+
+const WAVE_COLOR: Color32 = Color32::from_rgb(215, 173, 29);
+const SINE_POINTS: usize = 48;
+
+/// One cycle of an oscillator waveform at the given pulse width, drawn without antialiasing.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct WaveformDisplay {
+    waveform: WaveForm,
+    pulse_width: f32,
+    size: Vec2,
+}
+
+impl WaveformDisplay {
+    pub fn new(waveform: WaveForm, pulse_width: f32) -> Self {
+        Self {
+            waveform,
+            pulse_width,
+            size: vec2(64.0, 16.0),
+        }
+    }
+
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Points of the cycle as (phase 0..1, level -1..1)
+    fn cycle(&self) -> Vec<(f32, f32)> {
+        match self.waveform {
+            WaveForm::Saw => vec![(0.0, -1.0), (1.0, 1.0), (1.0, -1.0)],
+            WaveForm::Square => {
+                let pw = self.pulse_width.clamp(0.0, 1.0);
+                vec![(0.0, 1.0), (pw, 1.0), (pw, -1.0), (1.0, -1.0), (1.0, 1.0)]
+            }
+            _ => (0..=SINE_POINTS)
+                .map(|i| {
+                    let phase = i as f32 / SINE_POINTS as f32;
+                    (phase, (std::f32::consts::TAU * phase).sin())
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Widget for WaveformDisplay {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, response) = ui.allocate_exact_size(self.size, Sense::hover());
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+            painter.rect_filled(rect, 2.0, Color32::from_black_alpha(96));
+            let wave = rect.shrink(2.0);
+            let points = self
+                .cycle()
+                .into_iter()
+                .map(|(phase, level)| {
+                    pos2(
+                        lerp(wave.left()..=wave.right(), phase),
+                        lerp(wave.bottom()..=wave.top(), (level + 1.0) * 0.5),
+                    )
+                })
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, WAVE_COLOR)));
+        }
+
+        response
+    }
+}
@@ -0,0 +1,69 @@
+use nih_plug::prelude::{ParamSetter, Params};
+use serde::{Deserialize, Serialize};
+
+use super::param_snapshot;
+
+/// One parameter driven by an XY pad axis. The axis sweeps the parameter's normalized value from
+/// `min` to `max`, so a reversed range inverts the direction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct XyTarget {
+    pub id: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl XyTarget {
+    pub fn new(id: impl Into<String>) -> Self {
+        XyTarget {
+            id: id.into(),
+            min: 0.0,
+            max: 1.0,
+        }
+    }
+
+    pub fn value_at(&self, position: f32) -> f32 {
+        (self.min + (self.max - self.min) * position).clamp(0.0, 1.0)
+    }
+}
+
+/// XY pad assignments, saved with the plugin state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct XyMacro {
+    pub x: Vec<XyTarget>,
+    pub y: Vec<XyTarget>,
+    pub position: (f32, f32),
+}
+
+impl Default for XyMacro {
+    fn default() -> Self {
+        XyMacro {
+            x: vec![XyTarget::new("FilterCutoff")],
+            y: vec![XyTarget::new("FilterResonance")],
+            position: (0.5, 0.5),
+        }
+    }
+}
+
+impl XyMacro {
+    /// Move the pad and set every assigned parameter. Ids that don't match a parameter are
+    /// ignored.
+    pub fn set_position(
+        &mut self,
+        setter: &ParamSetter,
+        params: &impl Params,
+        position: (f32, f32),
+    ) {
+        self.position = position;
+        let param_map = params.param_map();
+        let targets = self
+            .x
+            .iter()
+            .map(|target| (target, position.0))
+            .chain(self.y.iter().map(|target| (target, position.1)));
+        for (target, axis_position) in targets {
+            if let Some((_, ptr, _)) = param_map.iter().find(|(id, _, _)| *id == target.id) {
+                param_snapshot::set_normalized(setter, *ptr, target.value_at(axis_position));
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use nih_plug_egui::egui::*;
+
+const HANDLE_COLOR: Color32 = Color32::from_rgb(215, 173, 29);
+
+/// Combined into one function (rather than two) to make it easier
+/// for the borrow checker. Values are normalized (x, y), with y = 1 at the top.
+type GetSetValue<'a> = Box<dyn 'a + FnMut(Option<(f32, f32)>) -> (f32, f32)>;
+
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct XyPad<'a> {
+    get_set_value: GetSetValue<'a>,
+    size: Vec2,
+}
+
+impl<'a> XyPad<'a> {
+    pub fn from_get_set(get_set_value: impl 'a + FnMut(Option<(f32, f32)>) -> (f32, f32)) -> Self {
+        Self {
+            get_set_value: Box::new(get_set_value),
+            size: vec2(128.0, 128.0),
+        }
+    }
+
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+}
+
+impl<'a> Widget for XyPad<'a> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        let (rect, mut response) = ui.allocate_exact_size(self.size, Sense::click_and_drag());
+
+        if let Some(pointer_position) = response.interact_pointer_pos() {
+            let x = remap_clamp(pointer_position.x, rect.left()..=rect.right(), 0.0..=1.0);
+            let y = remap_clamp(pointer_position.y, rect.bottom()..=rect.top(), 0.0..=1.0);
+            (self.get_set_value)(Some((x, y)));
+            response.mark_changed();
+        }
+
+        // Paint it:
+        if ui.is_rect_visible(rect) {
+            let (x, y) = (self.get_set_value)(None);
+            let visuals = ui.style().interact(&response);
+            let handle = pos2(
+                lerp(rect.left()..=rect.right(), x.clamp(0.0, 1.0)),
+                lerp(rect.bottom()..=rect.top(), y.clamp(0.0, 1.0)),
+            );
+            let crosshair = Stroke::new(1.0, HANDLE_COLOR.linear_multiply(0.4));
+            let painter = ui.painter();
+            painter.rect(
+                rect,
+                visuals.rounding,
+                ui.visuals().widgets.inactive.bg_fill,
+                visuals.bg_stroke,
+            );
+            painter.line_segment(
+                [pos2(rect.left(), handle.y), pos2(rect.right(), handle.y)],
+                crosshair,
+            );
+            painter.line_segment(
+                [pos2(handle.x, rect.top()), pos2(handle.x, rect.bottom())],
+                crosshair,
+            );
+            painter.circle_filled(handle, 6.0, HANDLE_COLOR);
+        }
+
+        response
+    }
+}
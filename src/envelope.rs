@@ -1,5 +1,8 @@
 use std::time::Instant;
 
+// Release snaps to idle below this level (-60 dB) instead of waiting for the curve to reach zero.
+const RELEASE_IDLE_LEVEL: f32 = 0.001;
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum State {
     Idle,
@@ -25,6 +28,7 @@ pub struct AdsrEnvelope {
     level: f32,
     start_time: Option<Instant>,
     pub params: Adsr,
+    oneshot: bool, // Ignore gate off and decay all the way to zero
 
     attack_coeff: f32,
     decay_coeff: f32,
@@ -43,6 +47,7 @@ impl AdsrEnvelope {
             voice_id,
             state: State::Idle,
             start_time: None,
+            oneshot: false,
             level: 0.0,
             params: Adsr {
                 attack_rate: 0.0,
@@ -83,21 +88,43 @@ impl AdsrEnvelope {
         self.attack_coeff = calc_coeff(self.params.attack_rate * sample_rate, self.target_ratio_a);
         self.attack_base = (1.0 + self.target_ratio_a) * (1.0 - self.attack_coeff);
 
+        let decay_target = if self.oneshot {
+            0.0
+        } else {
+            self.params.sustain_level
+        };
         self.decay_coeff = calc_coeff(self.params.decay_rate * sample_rate, self.target_ratio_dr);
-        self.decay_base =
-            (self.params.sustain_level - self.target_ratio_dr) * (1.0 - self.decay_coeff);
+        self.decay_base = (decay_target - self.target_ratio_dr) * (1.0 - self.decay_coeff);
 
         self.release_coeff =
             calc_coeff(self.params.release_rate * sample_rate, self.target_ratio_dr);
         self.release_base = -self.target_ratio_dr * (1.0 - self.release_coeff);
     }
 
+    /// In one-shot mode the envelope ignores note off and runs attack and decay down to zero,
+    /// regardless of the sustain level. Call before `set_envelope_parameters`.
+    pub fn set_oneshot(&mut self, oneshot: bool) {
+        self.oneshot = oneshot;
+    }
+
     pub fn gate_on(&mut self) {
+        // The attack curve rises from any level in 0..1 without overshooting, but never let a
+        // level outside that range (e.g. sustain changed mid-note) step or stick the attack
+        self.level = self.level.clamp(0.0, 1.0);
         self.start_time = Some(Instant::now());
         self.state = State::Attacking;
     }
 
+    /// Gate on and attack from zero, where `gate_on` attacks from the current level.
+    pub fn gate_on_from_zero(&mut self) {
+        self.level = 0.0;
+        self.gate_on();
+    }
+
     pub fn gate_off(&mut self) {
+        if self.oneshot {
+            return;
+        }
         match self.state {
             State::Attacking | State::Sustaining | State::Decaying => {
                 self.state = State::Releasing;
@@ -110,6 +137,14 @@ impl AdsrEnvelope {
         self.state == State::Idle
     }
 
+    /// Gate is on, i.e. the envelope has been triggered and not yet released.
+    pub fn is_gate_on(&self) -> bool {
+        matches!(
+            self.state,
+            State::Attacking | State::Decaying | State::Sustaining
+        )
+    }
+
     pub fn is_decaying(&self) -> bool {
         self.state == State::Decaying
     }
@@ -130,14 +165,18 @@ impl AdsrEnvelope {
             }
             State::Decaying => {
                 self.level = self.decay_base + self.level * self.decay_coeff;
-                if self.level <= self.params.sustain_level {
+                if self.oneshot && self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.state = State::Idle;
+                    self.start_time = None;
+                } else if !self.oneshot && self.level <= self.params.sustain_level {
                     self.level = self.params.sustain_level;
                     self.state = State::Sustaining;
                 }
             }
             State::Releasing => {
                 self.level = self.release_base + self.level * self.release_coeff;
-                if self.level <= 0.0 {
+                if self.level <= RELEASE_IDLE_LEVEL {
                     self.level = 0.0;
                     self.state = State::Idle;
                     self.start_time = None;
@@ -148,9 +187,152 @@ impl AdsrEnvelope {
     }
 }
 
+// A zero, negative or NaN rate gives a zero coefficient, which jumps past the target in a single
+// sample so the stage always completes on the next step.
 fn calc_coeff(rate: f32, target_ratio: f32) -> f32 {
-    if rate <= 0.0 {
+    if !(rate > 0.0) {
         return 0.0;
     }
     (-((1.0 + target_ratio) / target_ratio).ln() / rate).exp()
 }
+
+#[allow(unused)]
+mod tests {
+    use super::{AdsrEnvelope, State};
+
+    // Runs the envelope until it leaves `state`, returning the number of samples taken.
+    fn samples_in_state(env: &mut AdsrEnvelope, state: State, max_samples: usize) -> usize {
+        let mut n = 0;
+        while env.state == state {
+            env.next();
+            n += 1;
+            assert!(n <= max_samples, "Stuck in {:?}", state);
+        }
+        n
+    }
+
+    // With zero sustain the note decays to true silence and stays there while held.
+    #[test]
+    fn zero_sustain_decays_to_silence() {
+        let mut env = AdsrEnvelope::new(0);
+        env.set_envelope_parameters(44100.0, 0.01, 0.2, 0.0, 0.2);
+        env.gate_on();
+        samples_in_state(&mut env, State::Attacking, 44100);
+        samples_in_state(&mut env, State::Decaying, 44100);
+        assert_eq!(env.state, State::Sustaining);
+        for _ in 0..1000 {
+            assert_eq!(env.next(), 0.0);
+        }
+    }
+
+    // The shortest (and degenerate) envelope times move through every stage promptly and always
+    // end up idle after gate off.
+    #[test]
+    fn minimum_times_reach_idle() {
+        let sample_rate = 44100.0;
+        // Down to a fraction of a sample, as the fast filter envelope can go
+        for time in [0.001, 0.0001, 0.00001, 0.0, -1.0, f32::NAN] {
+            for sustain in [0.0, 0.5, 1.0] {
+                let mut env = AdsrEnvelope::new(0);
+                env.set_envelope_parameters(sample_rate, time, time, sustain, time);
+                env.gate_on();
+                samples_in_state(&mut env, State::Attacking, 100);
+                samples_in_state(&mut env, State::Decaying, 100);
+                assert_eq!(env.state, State::Sustaining);
+                assert_eq!(env.next(), sustain);
+
+                env.gate_off();
+                samples_in_state(&mut env, State::Releasing, 100);
+                assert_eq!(env.state, State::Idle);
+                assert_eq!(env.next(), 0.0);
+            }
+        }
+    }
+
+    // A release starting anywhere in the envelope continues from the current level, so the
+    // filter envelope contribution to cutoff never jumps at gate off.
+    #[test]
+    fn release_continues_smoothly_from_current_level() {
+        let sample_rate = 44100.0;
+        // Gate off during attack, decay and sustain
+        for gate_off_at in [100, 1000, 4000, 20000] {
+            let mut env = AdsrEnvelope::new(0);
+            env.set_envelope_parameters(sample_rate, 0.05, 0.05, 0.5, 0.05);
+            env.gate_on();
+            let mut previous = 0.0;
+            for _ in 0..gate_off_at {
+                previous = env.next();
+            }
+            env.gate_off();
+            assert_eq!(env.state, State::Releasing);
+            for _ in 0..100 {
+                let level = env.next();
+                assert!(level <= previous);
+                assert!(previous - level < 0.01);
+                previous = level;
+            }
+        }
+    }
+
+    // Retriggering during the release attacks from the current level, or from zero after
+    // gate_on_from_zero.
+    #[test]
+    fn retrigger_continues_or_resets_level() {
+        for reset in [false, true] {
+            let mut env = AdsrEnvelope::new(0);
+            env.set_envelope_parameters(44100.0, 0.05, 0.05, 0.5, 0.5);
+            env.gate_on();
+            samples_in_state(&mut env, State::Attacking, 44100);
+            env.gate_off();
+            let mut previous = 0.0;
+            for _ in 0..100 {
+                previous = env.next();
+            }
+            if reset {
+                env.gate_on_from_zero();
+            } else {
+                env.gate_on();
+            }
+            let level = env.next();
+            assert_eq!(env.state, State::Attacking);
+            if reset {
+                assert!(level < 0.01);
+            } else {
+                assert!(level >= previous);
+            }
+        }
+    }
+
+    // Retriggering at any point of the release attacks smoothly up from the current level to
+    // full, without clicks, overshoot or getting stuck at the top.
+    #[test]
+    fn retrigger_during_release_attacks_monotonically() {
+        for release_samples in [0, 10, 100, 1000, 5000, 20000] {
+            let mut env = AdsrEnvelope::new(0);
+            env.set_envelope_parameters(44100.0, 0.01, 0.1, 0.8, 0.5);
+            env.gate_on();
+            samples_in_state(&mut env, State::Attacking, 44100);
+            env.gate_off();
+            let mut previous = env.next();
+            for _ in 0..release_samples {
+                previous = env.next();
+            }
+            env.gate_on();
+            while env.state == State::Attacking {
+                let level = env.next();
+                assert!(level >= previous);
+                assert!(level <= 1.0);
+                assert!(
+                    level - previous < 0.05,
+                    "Attack stepped {} to {}",
+                    previous,
+                    level
+                );
+                previous = level;
+            }
+            assert_eq!(previous, 1.0);
+            assert_eq!(env.state, State::Decaying);
+            assert!(env.next() < 1.0);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+/// A mono filter processing one sample at a time.
 pub trait Filter {
+    /// Filter one sample. `cutoff` is in Hz and is clamped to the Nyquist frequency, `resonance`
+    /// is 0..1 where 1 is at the edge of self oscillation.
     fn process(&mut self, in_sample: f32, sample_rate: f32, cutoff: f32, resonance: f32) -> f32;
 }
@@ -0,0 +1,119 @@
+// Output freeze. The last moments of the voice output are always being recorded, and freezing
+// loops them under whatever is played next. The end of the loop crossfades into its start, so a
+// held chord loops without a click at the seam.
+
+const LOOP_SECONDS: f32 = 1.0;
+const SEAM_SECONDS: f32 = 0.1;
+const FADE_SECONDS: f32 = 0.05; // Fade in and out when freezing and unfreezing
+
+#[derive(Default)]
+pub struct Freeze {
+    buffer: Vec<(f32, f32)>, // Ring of loop + seam samples, the loop starts at `start`
+    loop_len: usize,
+    seam_len: usize,
+    write_pos: usize,
+    start: usize, // Oldest sample in the ring when frozen
+    play_pos: usize,
+    frozen: bool,
+    gain: f32,
+    fade_step: f32,
+}
+
+impl Freeze {
+    /// Allocates the recording, so call this outside of the audio thread.
+    pub fn new(sample_rate: f32) -> Self {
+        let loop_len = (LOOP_SECONDS * sample_rate) as usize;
+        let seam_len = (SEAM_SECONDS * sample_rate) as usize;
+        Freeze {
+            buffer: vec![(0.0, 0.0); loop_len + seam_len],
+            loop_len,
+            seam_len,
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate),
+            ..Default::default()
+        }
+    }
+
+    /// Freezing loops what was just recorded, unfreezing fades the loop out then records again.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        if frozen && !self.frozen && self.gain == 0.0 {
+            self.start = self.write_pos;
+            self.play_pos = 0;
+        }
+        self.frozen = frozen;
+    }
+
+    /// Nothing to add to the output, and recording
+    pub fn is_idle(&self) -> bool {
+        !self.frozen && self.gain == 0.0
+    }
+
+    /// Records `input` while not frozen, otherwise returns the loop to mix in.
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        if self.buffer.is_empty() {
+            return (0.0, 0.0);
+        }
+        if self.is_idle() {
+            self.buffer[self.write_pos] = input;
+            self.write_pos = (self.write_pos + 1) % self.buffer.len();
+            return (0.0, 0.0);
+        }
+
+        self.gain = if self.frozen {
+            (self.gain + self.fade_step).min(1.0)
+        } else {
+            (self.gain - self.fade_step).max(0.0)
+        };
+
+        // The seam plays the tail after the loop fading into its start
+        let at = |pos: usize| self.buffer[(self.start + pos) % self.buffer.len()];
+        let mut sample = at(self.play_pos);
+        if self.play_pos < self.seam_len {
+            let mix = self.play_pos as f32 / self.seam_len as f32;
+            let tail = at(self.loop_len + self.play_pos);
+            sample = (
+                tail.0 + (sample.0 - tail.0) * mix,
+                tail.1 + (sample.1 - tail.1) * mix,
+            );
+        }
+        self.play_pos = (self.play_pos + 1) % self.loop_len;
+        (sample.0 * self.gain, sample.1 * self.gain)
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.fill((0.0, 0.0));
+        self.frozen = false;
+        self.gain = 0.0;
+    }
+}
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loop_is_continuous_across_the_seam() {
+        let sample_rate = 1000.0;
+        let mut freeze = Freeze::new(sample_rate);
+        // A ramp, so any jump in the looped output shows up
+        for i in 0..5000 {
+            freeze.process((i as f32 / 1000.0, 0.0));
+        }
+        freeze.set_frozen(true);
+        let fade = (FADE_SECONDS * sample_rate) as usize;
+        for _ in 0..fade {
+            freeze.process((0.0, 0.0));
+        }
+        let mut last = freeze.process((0.0, 0.0)).0;
+        for _ in 0..3000 {
+            let sample = freeze.process((0.0, 0.0)).0;
+            assert!((sample - last).abs() < 0.02);
+            last = sample;
+        }
+
+        freeze.set_frozen(false);
+        for _ in 0..fade + 1 {
+            freeze.process((0.0, 0.0));
+        }
+        assert!(freeze.is_idle());
+    }
+}
@@ -6,6 +6,7 @@ use std::f64::consts::PI;
 // https://github.com/ddiakopoulos/MoogLadders
 // (LGPLv3)
 
+/// Non-linear 4-pole (24 dB/oct) Moog ladder lowpass filter, see [`Filter::process`].
 pub struct HuovilainenMoog {
     stage: [f64; 4],
     stage_tanh: [f64; 3],
@@ -16,11 +17,22 @@ pub struct HuovilainenMoog {
     res_quad: f64,
     coeff_cutoff: f32,
     coeff_resonance: f32,
+    oversampling: usize,
+    res_comp: bool,
+    res_comp_gain: f64,
+    cutoff_comp: bool,
 }
 
 const THERMAL: f64 = 0.000025f64;
+// Fraction of the resonance passband loss made up by the compensation. The DC gain of the ladder
+// is 1 / (1 + res_quad), full compensation would make the resonant peak very loud.
+const RES_COMP_AMOUNT: f64 = 0.5;
+// Below this resonance there is hardly a resonant peak to keep in tune, and the cutoff
+// compensation fades out towards zero resonance instead of raising the cutoff without bound.
+const CUTOFF_COMP_MIN_RESONANCE: f32 = 0.25;
 
 impl HuovilainenMoog {
+    /// New filter with zeroed state and 2x oversampling.
     pub fn new() -> Self {
         HuovilainenMoog {
             stage: [0.0; 4],
@@ -32,6 +44,39 @@ impl HuovilainenMoog {
 
             coeff_cutoff: 0.0,
             coeff_resonance: 0.0,
+            oversampling: 2,
+            res_comp: false,
+            res_comp_gain: 1.0,
+            cutoff_comp: false,
+        }
+    }
+
+    /// Number of filter iterations per sample (default 2). The cutoff coefficient is scaled to match.
+    pub fn set_oversampling(&mut self, oversampling: usize) {
+        if oversampling != self.oversampling {
+            self.oversampling = oversampling;
+            // Force coefficient update
+            self.coeff_cutoff = -1.0;
+        }
+    }
+
+    /// Boost the output as resonance increases, to make up for the level loss in the passband.
+    pub fn set_resonance_compensation(&mut self, res_comp: bool) {
+        if res_comp != self.res_comp {
+            self.res_comp = res_comp;
+            // Force coefficient update
+            self.coeff_cutoff = -1.0;
+        }
+    }
+
+    /// Raise the cutoff as resonance decreases so the resonant peak stays at the cutoff
+    /// frequency. Without it the peak falls below the cutoff at lower resonance, so resonant
+    /// patches play out of tune as the resonance is swept.
+    pub fn set_cutoff_compensation(&mut self, cutoff_comp: bool) {
+        if cutoff_comp != self.cutoff_comp {
+            self.cutoff_comp = cutoff_comp;
+            // Force coefficient update
+            self.coeff_cutoff = -1.0;
         }
     }
 
@@ -40,10 +85,15 @@ impl HuovilainenMoog {
             return;
         }
 
-        let total_cutoff = clamp(cutoff, 0.0, sample_rate / 2.0) as f64;
+        let tuned_cutoff = if self.cutoff_comp {
+            cutoff * resonance_tuning(resonance)
+        } else {
+            cutoff
+        };
+        let total_cutoff = clamp(tuned_cutoff, 0.0, sample_rate / 2.0) as f64;
 
         let fc = total_cutoff / sample_rate as f64;
-        let f = fc * 0.5; // oversampled
+        let f = fc / self.oversampling as f64; // oversampled
         let fc2 = fc * fc;
         let fc3 = fc * fc * fc;
 
@@ -53,6 +103,11 @@ impl HuovilainenMoog {
         self.tune = (1.0 - (-((2.0 * PI) * f * fcr)).exp()) / THERMAL;
 
         self.res_quad = 4.0 * resonance as f64 * self.acr;
+        self.res_comp_gain = if self.res_comp {
+            1.0 + RES_COMP_AMOUNT * self.res_quad
+        } else {
+            1.0
+        };
 
         // Cache the coeffs for the
         self.coeff_cutoff = cutoff;
@@ -60,12 +115,18 @@ impl HuovilainenMoog {
     }
 }
 
+impl Default for HuovilainenMoog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Filter for HuovilainenMoog {
     fn process(&mut self, in_sample: f32, sample_rate: f32, cutoff: f32, resonance: f32) -> f32 {
         self.compute_coeffs(cutoff, resonance, sample_rate);
 
         // Oversample
-        for _j in 0..2 {
+        for _j in 0..self.oversampling {
             let input = in_sample as f64 - self.res_quad * self.delay[5];
             self.stage[0] =
                 self.delay[0] + self.tune * (tanh(input * THERMAL) - self.stage_tanh[0]);
@@ -87,7 +148,18 @@ impl Filter for HuovilainenMoog {
             self.delay[5] = (self.stage[3] + self.delay[4]) * 0.5;
             self.delay[4] = self.stage[3];
         }
-        self.delay[5] as f32
+        (self.delay[5] * self.res_comp_gain) as f32
+    }
+}
+
+/// Cutoff multiplier putting the resonant peak at the cutoff. The resonant poles of the ladder ring
+/// at cutoff * resonance^(1/4), reaching the cutoff only at the edge of self oscillation.
+fn resonance_tuning(resonance: f32) -> f32 {
+    let resonance = resonance.clamp(0.0, 1.0);
+    if resonance >= CUTOFF_COMP_MIN_RESONANCE {
+        resonance.powf(-0.25)
+    } else {
+        1.0 + (CUTOFF_COMP_MIN_RESONANCE.powf(-0.25) - 1.0) * resonance / CUTOFF_COMP_MIN_RESONANCE
     }
 }
 
@@ -101,3 +173,48 @@ fn tanh(x: f64) -> f64 {
 
     a / (1.0 + (a * a)).sqrt()
 }
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+
+    // Frequency of the ringing after an impulse, from the spacing of its upward zero crossings
+    fn ring_frequency(filter: &mut HuovilainenMoog, cutoff: f32, resonance: f32) -> f32 {
+        let sample_rate = 44100.0;
+        let mut previous = filter.process(1.0, sample_rate, cutoff, resonance);
+        let mut crossings = Vec::new();
+        for i in 1..sample_rate as usize {
+            let y = filter.process(0.0, sample_rate, cutoff, resonance);
+            if previous <= 0.0 && y > 0.0 {
+                crossings.push(i as f32 - y / (y - previous));
+            }
+            previous = y;
+            if crossings.len() == 10 {
+                break;
+            }
+        }
+        let cycles = (crossings.len() - 2) as f32;
+        cycles / (crossings[crossings.len() - 1] - crossings[1]) * sample_rate
+    }
+
+    #[test]
+    fn cutoff_compensation_keeps_resonant_pitch() {
+        let cutoff = 1000.0;
+        for comp in [false, true] {
+            let mut low = HuovilainenMoog::new();
+            low.set_cutoff_compensation(comp);
+            let mut high = HuovilainenMoog::new();
+            high.set_cutoff_compensation(comp);
+            let low_freq = ring_frequency(&mut low, cutoff, 0.4);
+            let high_freq = ring_frequency(&mut high, cutoff, 1.0);
+            // Within 20 cents when compensated, more than a semitone apart when not
+            let cents = 1200.0 * (high_freq / low_freq).log2().abs();
+            if comp {
+                assert!(cents < 20.0, "{} Hz vs {} Hz", low_freq, high_freq);
+                assert!((1200.0 * (high_freq / cutoff).log2()).abs() < 20.0);
+            } else {
+                assert!(cents > 100.0, "{} Hz vs {} Hz", low_freq, high_freq);
+            }
+        }
+    }
+}
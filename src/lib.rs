@@ -1,27 +1,60 @@
 #![feature(once_cell)]
 mod blep;
+mod controllers;
 mod editor;
 mod envelope;
 mod filter;
+mod freeze;
 mod huovilainen;
+mod master_cut;
 mod midi;
 mod oscillator;
+pub mod param_info;
+mod param_smoothing;
+mod presets;
+mod reverb;
+mod svf;
 mod voice;
-use editor::{create_editor, frame_history::FrameHistory, SynthUiState};
+mod widener;
+pub use filter::Filter;
+pub use huovilainen::HuovilainenMoog;
+
+use atomic_float::AtomicF32;
+use controllers::Controllers;
+use editor::{
+    brightness_macro::BrightnessMacro, create_editor, edit_history::EditHistory,
+    frame_history::FrameHistory, param_clipboard::ParamClipboard, param_snapshot,
+    param_snapshot::AbCompare, xy_macro::XyMacro, SynthUiState,
+};
+use freeze::Freeze;
+use master_cut::MasterCut;
 use nih_plug::prelude::*;
 use nih_plug_egui::{create_egui_editor, EguiState};
 use oscillator::WaveForm;
+use param_smoothing::ParamSmoothers;
+use presets::SynthPresetBank;
 use rand::Rng;
 use rand_pcg::Pcg32;
+use reverb::Reverb;
 use std::{
     borrow::BorrowMut,
-    sync::{atomic::AtomicU16, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::SystemTime,
 };
 use voice::{Voice, MAX_UNISON};
+use widener::Widener;
 
 const NUM_VOICES: u32 = 16;
-const MAX_BLOCK_SIZE: usize = 64;
+const MOD_WHEEL_CC: u8 = 1;
+const SUSTAIN_CC: u8 = 64;
+const MIN_STEAL_AGE_SECONDS: f32 = 0.005; // Just started voices are stolen last
+const OSC_METER_RELEASE_SECONDS: f32 = 0.3;
+const WIDENER_FADE_SECONDS: f32 = 0.01; // Crossfade when mono widen moves off or back to 0
+const MAX_BLOCK_SIZE: usize = 256; // Largest selectable internal block, sizes the scratch buffers
+const FAST_ENV_TIME: f32 = 0.0001; // Shortest filter envelope attack and decay, for percussive zaps
 
 #[derive(Default)]
 pub enum EditText {
@@ -30,13 +63,31 @@ pub enum EditText {
     Editing(String, u64),
 }
 
-struct Synth {
+pub struct Synth {
     params: Arc<SynthParams>,
+    seed: u64,
     prng: Pcg32,
-    voices: Vec<Voice>,
+    analog_prng: Pcg32, // Analog instability/drift, separate stream from the oscillator phases
+    voices: Vec<Voice>, // Empty until initialize, so instances that never play stay small
     time: f64,
     ui_state: Arc<SynthUiState>,
     env_chg: Arc<AtomicU16>, // Dirty flag for ADSR envelope, per voice (1=dirty, 0=updated)
+    latched: [bool; 128],    // Notes released while latch is on, still sounding
+    latch_on: bool,          // Latch state as of the last process call
+    reverb: Reverb,
+    widener: Widener,
+    widener_mix: f32, // Widened share of the output, 0..1
+    master_cut: MasterCut,
+    freeze: Freeze,
+    controllers: Controllers,
+    param_smoothers: ParamSmoothers,
+    reverb_on: bool,           // Reverb state as of the last process call
+    held_notes: Vec<(u8, u8)>, // Keys held in mono/paraphonic mode as (note, velocity), oldest first
+    tempo: f64,                // Host tempo in BPM as of the last process call
+    sample_rate: f32,
+    sample_clock: u64,    // Samples processed since start, up to the current block
+    tremolo_phase: f64,   // Master tremolo LFO, 0..1
+    program: Option<u32>, // Preset selected by the last MIDI program change
 }
 
 #[derive(Clone, Copy, PartialEq, Enum)]
@@ -59,6 +110,237 @@ pub enum LfoWaveFormParameter {
     Triangle,
 }
 
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum FilterRouting {
+    /// One 24 dB/oct ladder per channel
+    #[name = "24 dB"]
+    Parallel,
+    /// Two ladders in series per channel, 48 dB/oct
+    #[name = "48 dB"]
+    Series,
+}
+
+/// Internal processing block size. Smaller blocks give finer grained automation and modulation,
+/// larger blocks less per-block overhead.
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum BlockSize {
+    #[name = "16"]
+    B16,
+    #[name = "32"]
+    B32,
+    #[name = "64"]
+    B64,
+    #[name = "128"]
+    B128,
+    #[name = "256"]
+    B256,
+}
+
+impl BlockSize {
+    pub fn samples(self) -> usize {
+        match self {
+            BlockSize::B16 => 16,
+            BlockSize::B32 => 32,
+            BlockSize::B64 => 64,
+            BlockSize::B128 => 128,
+            BlockSize::B256 => MAX_BLOCK_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum Quality {
+    /// Full quality
+    #[name = "HQ"]
+    High,
+    /// Low CPU: no filter oversampling, shorter BLEP and no analog instability
+    Eco,
+}
+
+/// Note length for tempo synced times
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum NoteDivision {
+    #[name = "1/64"]
+    D64,
+    #[name = "1/32"]
+    D32,
+    #[name = "1/16T"]
+    D16T,
+    #[name = "1/16"]
+    D16,
+    #[name = "1/16."]
+    D16D,
+    #[name = "1/8T"]
+    D8T,
+    #[name = "1/8"]
+    D8,
+    #[name = "1/8."]
+    D8D,
+    #[name = "1/4T"]
+    D4T,
+    #[name = "1/4"]
+    D4,
+    #[name = "1/4."]
+    D4D,
+    #[name = "1/2"]
+    D2,
+    #[name = "1/1"]
+    D1,
+    #[name = "2/1"]
+    Bars2,
+    #[name = "4/1"]
+    Bars4,
+}
+
+impl NoteDivision {
+    /// Length in quarter notes
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteDivision::D64 => 1.0 / 16.0,
+            NoteDivision::D32 => 1.0 / 8.0,
+            NoteDivision::D16T => 1.0 / 6.0,
+            NoteDivision::D16 => 1.0 / 4.0,
+            NoteDivision::D16D => 3.0 / 8.0,
+            NoteDivision::D8T => 1.0 / 3.0,
+            NoteDivision::D8 => 1.0 / 2.0,
+            NoteDivision::D8D => 3.0 / 4.0,
+            NoteDivision::D4T => 2.0 / 3.0,
+            NoteDivision::D4 => 1.0,
+            NoteDivision::D4D => 3.0 / 2.0,
+            NoteDivision::D2 => 2.0,
+            NoteDivision::D1 => 4.0,
+            NoteDivision::Bars2 => 8.0,
+            NoteDivision::Bars4 => 16.0,
+        }
+    }
+
+    pub fn seconds(self, tempo: f64) -> f32 {
+        (self.beats() * 60.0 / tempo) as f32
+    }
+}
+
+/// Filter iterations per sample. More reduces aliasing from the ladder nonlinearity when driven.
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum FilterOversampling {
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+    #[name = "8x"]
+    X8,
+}
+
+impl FilterOversampling {
+    pub fn factor(self) -> usize {
+        match self {
+            FilterOversampling::X2 => 2,
+            FilterOversampling::X4 => 4,
+            FilterOversampling::X8 => 8,
+        }
+    }
+}
+
+/// Scale for quantized pitch modulation, with degrees counted from the played note
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    Pentatonic,
+    Octaves,
+}
+
+impl Scale {
+    fn degrees(self) -> &'static [f32] {
+        match self {
+            Scale::Chromatic => &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0],
+            Scale::Major => &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0],
+            Scale::Minor => &[0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0],
+            Scale::Pentatonic => &[0.0, 2.0, 4.0, 7.0, 9.0],
+            Scale::Octaves => &[0.0],
+        }
+    }
+
+    /// Snap an offset in semitones to the nearest degree of the scale
+    pub fn quantize(self, semitones: f32) -> f32 {
+        let octave = (semitones / 12.0).floor() * 12.0;
+        let within = semitones - octave;
+        let nearest = self
+            .degrees()
+            .iter()
+            .chain([12.0].iter())
+            .min_by(|a, b| (*a - within).abs().total_cmp(&(*b - within).abs()))
+            .unwrap();
+        octave + nearest
+    }
+}
+
+/// How the oscillator level knobs map to amplitude
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum OscLevelCurve {
+    /// The knob is the amplitude multiplier, as the levels have always worked
+    Linear,
+    /// Knob travel spread evenly over 60 dB, finer control of quiet levels
+    #[name = "dB"]
+    Decibel,
+}
+
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum UnisonDetuneMode {
+    /// Detune in cents, spread in Hz grows with pitch
+    Musical,
+    /// Detune in Hz, same beating rate across the keyboard
+    Hz,
+}
+
+/// Second filter, running in parallel with the ladder
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum Filter2Mode {
+    Off,
+    #[name = "LP"]
+    Lowpass,
+    #[name = "BP"]
+    Bandpass,
+    #[name = "HP"]
+    Highpass,
+}
+
+/// Where the filter envelope attack starts when a sounding voice is retriggered
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum EnvRetrigMode {
+    /// Attack from zero
+    Reset,
+    /// Attack from the current level
+    Continue,
+}
+
+/// What happens on a note on for a note that's already sounding
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum SameNoteMode {
+    /// Start another voice for the same note
+    Stack,
+    /// Retrigger the voice already playing the note
+    #[name = "Re-attack"]
+    Reattack,
+}
+
+/// What happens on a note on when every voice is playing
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum VoiceOverflow {
+    /// Take over the voice least likely to be missed
+    Steal,
+    /// Drop the new note, so the playing notes and tails ring out undisturbed
+    Ignore,
+}
+
+/// Which of the held keys sounds in mono mode
+#[derive(Clone, Copy, PartialEq, Enum)]
+pub enum MonoPriority {
+    Last,
+    Highest,
+    Lowest,
+}
+
 impl Into<WaveForm> for WaveFormParameter {
     fn into(self) -> WaveForm {
         match self {
@@ -83,18 +365,91 @@ impl Into<WaveForm> for LfoWaveFormParameter {
 pub struct SynthParams {
     #[persist = "editor-state"]
     editor_state: Arc<EguiState>,
+    #[persist = "performance-view"]
+    performance_view: Arc<AtomicBool>, // Editor shows the simplified performance layout
+    #[persist = "xy-macro"]
+    xy_macro: Mutex<XyMacro>,
+    #[persist = "brightness-macro"]
+    brightness_macro: Mutex<BrightnessMacro>,
+
+    // MIDI controller positions are persisted with the plugin state so held controllers survive
+    // a reload. Other runtime state (notes, LFO and envelope phases) is not persisted.
+    #[persist = "pitch-bend"]
+    pitch_bend: Arc<AtomicF32>, // -1..1
+    #[persist = "mod-wheel"]
+    mod_wheel: Arc<AtomicF32>, // 0..1
+    #[persist = "channel-pressure"]
+    channel_pressure: Arc<AtomicF32>, // 0..1
+
+    /// Time for each controller to glide to a newly received value
+    #[id = "BendSmoothing"]
+    bend_smoothing: FloatParam,
+    #[id = "ModWheelSmoothing"]
+    mod_wheel_smoothing: FloatParam,
+    #[id = "PressureSmoothing"]
+    pressure_smoothing: FloatParam,
+    #[id = "SustainSmoothing"]
+    sustain_smoothing: FloatParam,
+    /// Time for the cutoffs, resonance, oscillator levels, pulse widths, detunes and master gain
+    /// to glide to a new value. These are smoothed by the synth, not by nih-plug.
+    #[id = "ParamSmoothing"]
+    param_smoothing: FloatParam,
 
     // Filter
     #[id = "FilterCutoff"]
     filter_cutoff: FloatParam,
+    /// Lowest frequency the modulated cutoff can reach, so some signal always passes
+    #[id = "FilterCutoffFloor"]
+    filter_cutoff_floor: FloatParam,
     #[id = "FilterResonance"]
     filter_resonance: FloatParam,
     #[id = "FilterEnvModGain"]
     filter_env_mod_gain: FloatParam,
     #[id = "FilterKeyTrack"]
     filter_key_track: FloatParam,
+    /// Fixed random cutoff offset per voice, up to this many semitones, like the spread between
+    /// the filters of an analog polysynth
+    #[id = "FilterDrift"]
+    filter_drift: FloatParam,
     #[id = "FilterVelocityMod"]
     filter_velocity_mod: FloatParam,
+    /// Resonance boost following the filter envelope for hard played notes, from nothing at
+    /// velocity 64 up to the full amount at 127
+    #[id = "Accent"]
+    accent: FloatParam,
+    #[id = "Warmth"]
+    warmth: FloatParam,
+    #[id = "FilterEnvToPitch"]
+    filter_env_to_pitch: FloatParam,
+    /// Filter envelope to OSC1 and OSC2 pulse width, 100% moves it by 0.5
+    #[id = "FilterEnvToPw"]
+    filter_env_to_pw: FloatParam,
+    #[id = "FilterRouting"]
+    filter_routing: EnumParam<FilterRouting>,
+    /// Hold the filter modulation (envelope, LFO and velocity) of each voice where it is
+    #[id = "FilterFreeze"]
+    filter_freeze: BoolParam,
+    /// Sum both channels into a single filter, like a classic mono synth. The filter then sees
+    /// all unison voices together, and the unison stereo spread is brought back after it by
+    /// shifting the phase of the right channel. Pan is applied after the filter as usual.
+    #[id = "MonoFilter"]
+    mono_filter: BoolParam,
+    /// Make up the passband level lost to resonance. Off is the classic ladder behavior.
+    #[id = "ResComp"]
+    res_comp: BoolParam,
+    /// Keep the resonant peak at the cutoff frequency at every resonance, so resonant patches
+    /// stay in tune while the resonance moves
+    #[id = "ResTuneComp"]
+    res_tune_comp: BoolParam,
+    #[id = "Filter2Mode"]
+    filter2_mode: EnumParam<Filter2Mode>,
+    /// Filter 2 follows the same key tracking and modulation as the main filter, relative to its
+    /// own cutoff
+    #[id = "Filter2Cutoff"]
+    filter2_cutoff: FloatParam,
+    /// Mix between the main filter (0%) and filter 2 (100%)
+    #[id = "FilterBlend"]
+    filter_blend: FloatParam,
 
     // Amp Envelope
     #[id = "AmpEnvAttack"]
@@ -105,6 +460,22 @@ pub struct SynthParams {
     amp_env_sustain: FloatParam,
     #[id = "AmpEnvRelease"]
     amp_env_release: FloatParam,
+    #[id = "AmpEnvOneShot"]
+    amp_env_oneshot: BoolParam,
+    /// Notes hold their gate at least this long, so a note off arriving right after the note on
+    /// still gives an audible note
+    #[id = "MinNoteLength"]
+    min_note_length: FloatParam,
+    /// Floor for the amp release time, so short release settings still fade out instead of
+    /// clicking. The release parameter itself is left as set.
+    #[id = "MinRelease"]
+    min_release: FloatParam,
+    /// How much note velocity scales the amp sustain level. At 0 sustain ignores velocity.
+    #[id = "VelocityToSustain"]
+    velocity_to_sustain: FloatParam,
+    /// Level of the softest notes. Velocity scales the level between this and full.
+    #[id = "VelocityFloor"]
+    velocity_floor: FloatParam,
 
     // Filter envelope
     #[id = "FilterEnvAttack"]
@@ -115,8 +486,40 @@ pub struct SynthParams {
     filter_env_sustain: FloatParam,
     #[id = "FilterEnvRelease"]
     filter_env_release: FloatParam,
+    #[id = "FilterEnvRetrigMode"]
+    filter_env_retrig_mode: EnumParam<EnvRetrigMode>,
+
+    // Tempo synced envelope times, replacing the attack, decay and release seconds when on
+    #[id = "EnvSync"]
+    env_sync: BoolParam,
+    #[id = "AmpEnvAttackSync"]
+    amp_env_attack_sync: EnumParam<NoteDivision>,
+    #[id = "AmpEnvDecaySync"]
+    amp_env_decay_sync: EnumParam<NoteDivision>,
+    #[id = "AmpEnvReleaseSync"]
+    amp_env_release_sync: EnumParam<NoteDivision>,
+    #[id = "FilterEnvAttackSync"]
+    filter_env_attack_sync: EnumParam<NoteDivision>,
+    #[id = "FilterEnvDecaySync"]
+    filter_env_decay_sync: EnumParam<NoteDivision>,
+    #[id = "FilterEnvReleaseSync"]
+    filter_env_release_sync: EnumParam<NoteDivision>,
 
     // OSC1
+    #[id = "Osc1On"]
+    osc1_on: BoolParam,
+    /// Soloing any oscillator silences the ones that aren't soloed, whether they are on or not
+    #[id = "Osc1Solo"]
+    osc1_solo: BoolParam,
+    /// Correct the waveform and pulse width loudness differences to match a saw. Off by default so
+    /// existing patches keep their levels.
+    #[id = "OscLevelNormalize"]
+    osc_level_normalize: BoolParam,
+    #[id = "OscLevelCurve"]
+    osc_level_curve: EnumParam<OscLevelCurve>,
+    /// Mix the oscillator in after the filter instead of through it
+    #[id = "Osc1FilterBypass"]
+    osc1_filter_bypass: BoolParam,
     #[id = "Osc1Level"]
     osc1_level: FloatParam,
     #[id = "Osc1Octave"]
@@ -127,8 +530,17 @@ pub struct SynthParams {
     osc1_waveform: EnumParam<WaveFormParameter>,
     #[id = "Osc1PulseWidth"]
     osc1_pulsewidth: FloatParam,
+    /// Level of OSC1 an octave up mixed into OSC1, for organ like stacked octaves
+    #[id = "Osc1OctaveMix"]
+    osc1_octave_mix: FloatParam,
 
     // OSC1
+    #[id = "Osc2On"]
+    osc2_on: BoolParam,
+    #[id = "Osc2Solo"]
+    osc2_solo: BoolParam,
+    #[id = "Osc2FilterBypass"]
+    osc2_filter_bypass: BoolParam,
     #[id = "Osc2Level"]
     osc2_level: FloatParam,
     #[id = "Osc2Octave"]
@@ -139,91 +551,523 @@ pub struct SynthParams {
     osc2_waveform: EnumParam<WaveFormParameter>,
     #[id = "Osc2PulseWidth"]
     osc2_pulsewidth: FloatParam,
+    #[id = "PwKeyTrack"]
+    pw_keytrack: FloatParam,
+    /// Crossfade between free running and hard synced OSC2. OSC2 detune and octave set the
+    /// synced pitch, and each OSC2 unison voice syncs to the matching OSC1 unison voice.
+    #[id = "OscSync"]
+    osc_sync: FloatParam,
+    /// Detunes OSC1 down and OSC2 up by this many cents, on top of their own detune
+    #[id = "OscSpread"]
+    osc_spread: FloatParam,
 
     // LFO
     #[id = "LfoHostSync"]
     lfo_host_sync: BoolParam,
     #[id = "LfoKeyTrig"]
     lfo_key_trig: BoolParam,
+    /// With key trig, only the first note played while no other key is held restarts the LFO.
+    /// Notes added while it is held pick up its LFO phase, so a whole chord modulates together.
+    #[id = "LfoChordTrig"]
+    lfo_chord_trig: BoolParam,
     #[id = "LfoFreq"]
     lfo_freq: FloatParam,
+    /// LFO cycle length when synced to the host tempo
+    #[id = "LfoSyncDivision"]
+    lfo_sync_division: EnumParam<NoteDivision>,
+    /// Phase offset of the synced LFO against the beat, as a fraction of a cycle
+    #[id = "LfoSyncOffset"]
+    lfo_sync_offset: FloatParam,
+    /// Blend from the synced rate (0%) to the free rate (100%). Above 0% the LFO free runs at the
+    /// blended rate instead of following the transport, so it drifts off the grid.
+    #[id = "LfoSyncBlend"]
+    lfo_sync_blend: FloatParam,
     #[id = "LfoWaveform"]
     lfo_waveform: EnumParam<LfoWaveFormParameter>,
+    /// Warps the LFO cycle, 0% is the plain waveform
+    #[id = "LfoShape"]
+    lfo_shape: FloatParam,
     #[id = "LfoFilterModDepth"]
     lfo_filter_mod_depth: FloatParam,
+    /// Modulation by the LFO a quarter cycle ahead, the cosine of a sine LFO. With the sine on the
+    /// cutoff and the cosine on pan the sound moves in a circle.
+    #[id = "LfoCosFilterModDepth"]
+    lfo_cos_filter_mod_depth: FloatParam,
+    #[id = "LfoCosPanDepth"]
+    lfo_cos_pan_depth: FloatParam,
     #[id = "LfoOsc1DetuneModDepth"]
     lfo_osc1_detune_mod_depth: FloatParam,
+    #[id = "LfoOsc2DetuneModDepth"]
+    lfo_osc2_detune_mod_depth: FloatParam,
+    #[id = "LfoUnisonDetuneDepth"]
+    lfo_unison_detune_depth: FloatParam,
+    /// Snap the LFO pitch modulation of both oscillators to the notes of a scale, so it steps
+    /// through in key notes instead of sweeping
+    #[id = "PitchModQuantize"]
+    pitch_mod_quantize: BoolParam,
+    #[id = "PitchModScale"]
+    pitch_mod_scale: EnumParam<Scale>,
+    /// How much note velocity scales all LFO modulation depths. At 0 depth ignores velocity.
+    #[id = "VelocityToLfo"]
+    velocity_to_lfo: FloatParam,
 
     #[id = "MasterGain"]
     master_gain: FloatParam,
+    /// Frequency of A4 for the oscillators
+    #[id = "MasterTuning"]
+    master_tuning: FloatParam,
+    #[id = "MasterPan"]
+    master_pan: FloatParam,
+    /// Whole patch tremolo from an LFO of its own, the same for every voice and running whether
+    /// or not notes are held
+    #[id = "MasterTremoloDepth"]
+    master_tremolo_depth: FloatParam,
+    #[id = "MasterTremoloFreq"]
+    master_tremolo_freq: FloatParam,
+    #[id = "MasterTremoloSync"]
+    master_tremolo_sync: BoolParam,
+    /// Tremolo cycle length when synced to the host tempo
+    #[id = "MasterTremoloDivision"]
+    master_tremolo_division: EnumParam<NoteDivision>,
+    /// Width from shifting the phase of the right channel against the left. Unlike a delay this
+    /// keeps both levels and sums to mono without comb filtering.
+    #[id = "MonoWiden"]
+    mono_widen: FloatParam,
+    /// Gentle master low cut and high cut, out of circuit at 20 Hz and 20 kHz
+    #[id = "MasterLowCut"]
+    master_lowcut: FloatParam,
+    #[id = "MasterHighCut"]
+    master_highcut: FloatParam,
+    /// Output reverb, skipped entirely when off to save CPU
+    #[id = "ReverbOn"]
+    reverb_on: BoolParam,
+    #[id = "ReverbSize"]
+    reverb_size: FloatParam,
+    #[id = "ReverbDamping"]
+    reverb_damping: FloatParam,
+    #[id = "ReverbMix"]
+    reverb_mix: FloatParam,
+    #[id = "InvertLeft"]
+    invert_left: BoolParam,
+    #[id = "InvertRight"]
+    invert_right: BoolParam,
+    /// Soft clip each voice before summing, taming single loud voices
+    #[id = "VoiceSoftClip"]
+    voice_softclip: BoolParam,
 
     #[id = "UnisonVoices"]
     unison_voices: IntParam,
     #[id = "UnisonDetune"]
     unison_detune: FloatParam,
+    /// How much harder notes widen the unison detune and softer ones narrow it. Velocity 64 keeps
+    /// the set detune, at 100% velocity 127 doubles it and the softest notes have none. At 0
+    /// detune ignores velocity.
+    #[id = "VelocityToUnisonDetune"]
+    velocity_to_unison_detune: FloatParam,
+    #[id = "UnisonDetuneMode"]
+    unison_detune_mode: EnumParam<UnisonDetuneMode>,
     #[id = "UnisonStereoSpread"]
     unison_stereo_spread: FloatParam,
+    /// Without unison, fake the spread by shifting the phase of the right channel of each voice.
+    /// Much cheaper than more unison voices, and sums to mono without comb filtering.
+    #[id = "CheapStereo"]
+    cheap_stereo: BoolParam,
+    /// Random level offset per unison voice, drawn at note on
+    #[id = "UnisonLevelSpread"]
+    unison_level_spread: FloatParam,
+    /// Random pan position per note, drawn at note on
+    #[id = "NotePanRandom"]
+    note_pan_random: FloatParam,
+
+    /// Pitch bend range in semitones, separately for bending up and down
+    #[id = "BendRangeUp"]
+    bend_range_up: IntParam,
+    #[id = "BendRangeDown"]
+    bend_range_down: IntParam,
 
     #[id = "PolyMode"]
     poly_mode: BoolParam,
     #[id = "Portamento"]
     portamento: FloatParam,
+    #[id = "PitchStartDetune"]
+    pitch_env_start: FloatParam,
+    /// Pitch blip at note start: the pitch starts up to this many semitones high, scaled by
+    /// velocity, and falls back over the decay time
+    #[id = "PitchEnvDepth"]
+    pitch_env_depth: FloatParam,
+    #[id = "PitchEnvDecay"]
+    pitch_env_decay: FloatParam,
+    #[id = "OscRetrigLegato"]
+    osc_retrig_legato: BoolParam,
+    /// Start every note with all oscillators at phase 0 instead of random phases, for the same
+    /// attack transient on every note
+    #[id = "OscPhaseReset"]
+    osc_phase_reset: BoolParam,
+    #[id = "MonoPriority"]
+    mono_priority: EnumParam<MonoPriority>,
+    /// Two note paraphony on a single voice: OSC1 plays the lower and OSC2 the higher of the two
+    /// most recently held keys, through one filter and one set of envelopes
+    #[id = "Paraphonic"]
+    paraphonic: BoolParam,
+    #[id = "Latch"]
+    latch: BoolParam,
+    /// Loop the last second of output under new notes, fading out again when switched off
+    #[id = "Freeze"]
+    freeze: BoolParam,
+    #[id = "SameNoteMode"]
+    same_note_mode: EnumParam<SameNoteMode>,
+    #[id = "VoiceOverflow"]
+    voice_overflow: EnumParam<VoiceOverflow>,
+
+    #[id = "BlockSize"]
+    block_size: EnumParam<BlockSize>,
+    #[id = "Quality"]
+    quality: EnumParam<Quality>,
+    /// BLEP antialiasing of the saw and square. Off aliases audibly on high notes but is
+    /// noticeably cheaper per voice.
+    #[id = "Antialias"]
+    antialias: BoolParam,
+    /// Ignored in eco quality, which runs the filter without oversampling
+    #[id = "FilterOversample"]
+    filter_oversample: EnumParam<FilterOversampling>,
 }
 
 impl Default for Synth {
     fn default() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+}
+
+impl Synth {
+    /// Create a synth whose random number generators are all derived from `seed`, so that
+    /// rendering the same patch and notes twice gives identical output.
+    pub fn with_seed(seed: u64) -> Self {
         let e = Arc::new(AtomicU16::new(0b1111_1111_1111_1111));
         Self {
             params: Arc::new(SynthParams::new(e.clone())),
             time: 0.0,
-            prng: create_rng(),
-            env_chg: e.clone(),
-            voices: (0..NUM_VOICES)
-                .map(move |i| Voice::new(i as i32, 44100.0, &e.clone()))
-                .collect(),
+            seed,
+            prng: create_rng(seed, PRNG_STREAM),
+            analog_prng: create_rng(seed, ANALOG_PRNG_STREAM),
+            env_chg: e,
+            latched: [false; 128],
+            latch_on: false,
+            reverb: Reverb::default(),
+            widener: Widener::default(),
+            widener_mix: 0.0,
+            master_cut: MasterCut::default(),
+            freeze: Freeze::default(),
+            controllers: Controllers::default(),
+            param_smoothers: ParamSmoothers::default(),
+            reverb_on: false,
+            held_notes: Vec::with_capacity(128),
+            tempo: DEFAULT_TEMPO,
+            sample_rate: 44100.0,
+            sample_clock: 0,
+            tremolo_phase: 0.0,
+            program: None,
+            voices: Vec::new(),
             ui_state: Arc::new(SynthUiState {
                 edit_text: Mutex::new(EditText::None),
+                edit_history: Mutex::new(EditHistory::default()),
                 frame_history: Mutex::new(FrameHistory::default()),
+                correlation: AtomicF32::new(1.0),
+                active_voices: AtomicU32::new(0),
+                ab_compare: Mutex::new(AbCompare::default()),
+                preset_bank: Mutex::new(SynthPresetBank::default()),
+                preset_index: AtomicU32::new(0),
+                program_snapshots: Mutex::new(Vec::new()),
+                active_notes: [AtomicU64::new(0), AtomicU64::new(0)],
+                param_clipboard: Mutex::new(ParamClipboard::default()),
+                osc_levels: [AtomicF32::new(0.0), AtomicF32::new(0.0)],
             }),
         }
     }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn program(&self) -> Option<u32> {
+        self.program
+    }
+
+    /// Change the seed. Takes effect immediately and on every subsequent `reset()`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reseed();
+    }
+
+    fn reseed(&mut self) {
+        self.prng = create_rng(self.seed, PRNG_STREAM);
+        self.analog_prng = create_rng(self.seed, ANALOG_PRNG_STREAM);
+        self.draw_filter_drift();
+    }
+
+    /// Give every voice its own fixed filter offset, the same for a given seed.
+    fn draw_filter_drift(&mut self) {
+        let mut rng = self.derive_rng(FILTER_DRIFT_STREAM);
+        for voice in self.voices.iter_mut() {
+            voice.filter_drift = rng.gen_range(-1.0..=1.0);
+        }
+    }
+
+    /// A generator derived from the synth seed. Each randomized feature uses its own stream so
+    /// adding randomness in one place doesn't change the sequence seen by another.
+    pub fn derive_rng(&self, stream: u64) -> Pcg32 {
+        create_rng(self.seed, stream)
+    }
 }
 
-fn create_rng() -> Pcg32 {
-    Pcg32::new(111, 333)
+const DEFAULT_SEED: u64 = 111;
+const DEFAULT_TEMPO: f64 = 120.0; // Used when the host doesn't report a tempo
+const PRNG_STREAM: u64 = 333;
+const ANALOG_PRNG_STREAM: u64 = 334;
+const FILTER_DRIFT_STREAM: u64 = 335;
+
+fn create_rng(seed: u64, stream: u64) -> Pcg32 {
+    Pcg32::new(seed, stream)
 }
 
 impl SynthParams {
     fn new(env_chg: Arc<AtomicU16>) -> Self {
         Self {
             editor_state: editor::default_editor_state(),
+            performance_view: Arc::new(AtomicBool::new(false)),
+            xy_macro: Mutex::new(XyMacro::default()),
+            brightness_macro: Mutex::new(BrightnessMacro::default()),
+            pitch_bend: Arc::new(AtomicF32::new(0.0)),
+            mod_wheel: Arc::new(AtomicF32::new(0.0)),
+            channel_pressure: Arc::new(AtomicF32::new(0.0)),
+            bend_smoothing: smoothing_time_param("Bend Smoothing", 0.005),
+            mod_wheel_smoothing: smoothing_time_param("Mod Wheel Smoothing", 0.02),
+            pressure_smoothing: smoothing_time_param("Pressure Smoothing", 0.02),
+            sustain_smoothing: smoothing_time_param("Sustain Smoothing", 0.0),
+            param_smoothing: smoothing_time_param("Param Smoothing", 0.05),
 
-            filter_cutoff: freq_param("Filter Cutoff", 4000.0),
+            filter_cutoff: freq_param("Filter Cutoff", 4000.0).with_smoother(SmoothingStyle::None),
+            filter_cutoff_floor: freq_param("Filter Cutoff Floor", 20.0),
             master_gain: gain_param("Master", -6.0),
+            master_tuning: FloatParam::new(
+                "Master Tuning",
+                440.0,
+                FloatRange::Linear {
+                    min: 415.0,
+                    max: 466.0,
+                },
+            )
+            .with_step_size(0.01)
+            .with_value_to_string(v2s_f32_tuning()),
+            master_pan: symmetric_percentage_param("Pan")
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+            reverb_on: BoolParam::new("Reverb", false),
+            reverb_size: percentage_param("Reverb Size", 0.5),
+            reverb_damping: percentage_param("Reverb Damping", 0.5),
+            reverb_mix: percentage_param("Reverb Mix", 0.2),
+            mono_widen: percentage_param("Mono Widen", 0.0),
+            master_tremolo_depth: percentage_param("Master Tremolo", 0.0),
+            master_tremolo_freq: FloatParam::new(
+                "Master Tremolo Freq",
+                4.0,
+                FloatRange::Skewed {
+                    min: 0.01,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            master_tremolo_sync: BoolParam::new("Master Tremolo Sync", false),
+            master_tremolo_division: EnumParam::new("Master Tremolo Rate", NoteDivision::D8),
+            master_lowcut: FloatParam::new(
+                "Master Low Cut",
+                master_cut::LOWCUT_MIN,
+                FloatRange::Skewed {
+                    min: master_cut::LOWCUT_MIN,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            master_highcut: FloatParam::new(
+                "Master High Cut",
+                master_cut::HIGHCUT_MAX,
+                FloatRange::Skewed {
+                    min: 2000.0,
+                    max: master_cut::HIGHCUT_MAX,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            invert_left: BoolParam::new("Invert L", false),
+            invert_right: BoolParam::new("Invert R", false),
+            voice_softclip: BoolParam::new("Voice Soft Clip", false),
             amp_env_attack: env_time_param("Amp Attack", env_chg.clone()),
             amp_env_decay: env_time_param("Amp Decay", env_chg.clone()),
             amp_env_release: env_time_param("Amp Release", env_chg.clone()),
             amp_env_sustain: env_gain_param("Amp Sustain", env_chg.clone()),
-            filter_env_attack: env_time_param("Filter Attack", env_chg.clone()),
-            filter_env_decay: env_time_param("Filter Decay", env_chg.clone()),
+            amp_env_oneshot: BoolParam::new("Amp One-Shot", false).with_callback({
+                let env_chg = env_chg.clone();
+                Arc::new(move |_| env_chg.store(u16::MAX, std::sync::atomic::Ordering::Relaxed))
+            }),
+            min_note_length: FloatParam::new(
+                "Min Note Length",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 0.5,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.001)
+            .with_value_to_string(v2s_f32_ms_then_s(0, 2)),
+            min_release: FloatParam::new(
+                "Min Release",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.001)
+            .with_value_to_string(v2s_f32_ms_then_s(0, 2))
+            .with_callback({
+                let env_chg = env_chg.clone();
+                Arc::new(move |_| env_chg.store(u16::MAX, std::sync::atomic::Ordering::Relaxed))
+            }),
+            velocity_to_sustain: percentage_param("Vel to Sustain", 0.0).with_callback({
+                let env_chg = env_chg.clone();
+                Arc::new(move |_| env_chg.store(u16::MAX, std::sync::atomic::Ordering::Relaxed))
+            }),
+            velocity_floor: FloatParam::new(
+                "Velocity Floor",
+                midi::DEFAULT_VELOCITY_FLOOR,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: util::db_to_gain(-6.0),
+                    factor: FloatRange::gain_skew_factor(-80.0, -6.0),
+                },
+            )
+            .with_unit("dB")
+            .with_value_to_string(v2s_f32_gain_to_db_or_inf(1))
+            .with_string_to_value(s2v_f32_gain_to_db_or_inf()),
+            filter_env_attack: env_time_param_from("Filter Attack", FAST_ENV_TIME, env_chg.clone()),
+            filter_env_decay: env_time_param_from("Filter Decay", FAST_ENV_TIME, env_chg.clone()),
             filter_env_release: env_time_param("Filter Release", env_chg.clone()),
+            filter_env_retrig_mode: EnumParam::new("Filter Env Retrig", EnvRetrigMode::Continue),
             filter_env_sustain: env_gain_param("Filter Sustain", env_chg.clone()),
+            env_sync: BoolParam::new("Env Sync", false).with_callback({
+                let env_chg = env_chg.clone();
+                Arc::new(move |_| env_chg.store(u16::MAX, std::sync::atomic::Ordering::Relaxed))
+            }),
+            amp_env_attack_sync: env_division_param(
+                "Amp Attack Sync",
+                NoteDivision::D64,
+                env_chg.clone(),
+            ),
+            amp_env_decay_sync: env_division_param(
+                "Amp Decay Sync",
+                NoteDivision::D8,
+                env_chg.clone(),
+            ),
+            amp_env_release_sync: env_division_param(
+                "Amp Release Sync",
+                NoteDivision::D8,
+                env_chg.clone(),
+            ),
+            filter_env_attack_sync: env_division_param(
+                "Filter Attack Sync",
+                NoteDivision::D64,
+                env_chg.clone(),
+            ),
+            filter_env_decay_sync: env_division_param(
+                "Filter Decay Sync",
+                NoteDivision::D8,
+                env_chg.clone(),
+            ),
+            filter_env_release_sync: env_division_param(
+                "Filter Release Sync",
+                NoteDivision::D8,
+                env_chg.clone(),
+            ),
             filter_resonance: percentage_param("Filter Resonance", 0.1),
             filter_env_mod_gain: symmetric_percentage_param("Filter env mod"),
-            filter_key_track: percentage_param("Key track", 0.1),
+            filter_drift: FloatParam::new(
+                "Filter Drift",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 12.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" st")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            filter_key_track: percentage_param("Key track", 0.1)
+                .with_unit("")
+                .with_value_to_string(v2s_f32_keytrack()),
             filter_velocity_mod: percentage_param("Filter Vel", 0.1),
+            accent: percentage_param("Accent", 0.0),
+            warmth: percentage_param("Warmth", 0.0),
+            filter_env_to_pitch: FloatParam::new(
+                "Filter Env Pitch",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.01)
+            .with_unit("st")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            filter_env_to_pw: symmetric_percentage_param("Filter Env PW"),
+            filter_routing: EnumParam::new("Filter Slope", FilterRouting::Parallel),
+            filter_freeze: BoolParam::new("Filter Freeze", false),
+            mono_filter: BoolParam::new("Mono Filter", false),
+            res_comp: BoolParam::new("Res Comp", false),
+            res_tune_comp: BoolParam::new("Res Tune Comp", false),
+            filter2_mode: EnumParam::new("Filter 2 Mode", Filter2Mode::Off),
+            filter2_cutoff: freq_param("Filter 2 Cutoff", 1000.0)
+                .with_smoother(SmoothingStyle::None),
+            filter_blend: percentage_param("Filter Blend", 0.5),
+            osc1_on: BoolParam::new("Osc1 On", true),
+            osc1_solo: BoolParam::new("Osc1 Solo", false),
+            osc_level_normalize: BoolParam::new("Level Normalize", false),
+            osc_level_curve: EnumParam::new("Level Curve", OscLevelCurve::Linear),
+            osc1_filter_bypass: BoolParam::new("Osc1 Filter Bypass", false),
             osc1_level: gain_param("Osc1 Level", 0.0),
             osc1_octave: IntParam::new("Osc1 Octave", 0, IntRange::Linear { min: -2, max: 2 }),
             osc1_detune: fine_detune_param("Osc1 Detune"),
             osc1_waveform: EnumParam::new("Osc1 Waveform", WaveFormParameter::Saw),
             osc1_pulsewidth: percentage_param("Osc1 PW", 0.5),
+            osc1_octave_mix: percentage_param("Osc1 Octave Mix", 0.0),
+            osc2_on: BoolParam::new("Osc2 On", true),
+            osc2_solo: BoolParam::new("Osc2 Solo", false),
+            osc2_filter_bypass: BoolParam::new("Osc2 Filter Bypass", false),
             osc2_level: gain_param("Osc2 Level", 0.0),
             osc2_octave: IntParam::new("Osc2 Octave", 0, IntRange::Linear { min: -2, max: 2 }),
             osc2_detune: fine_detune_param("Osc2 Detune"),
             osc2_waveform: EnumParam::new("Osc2 Waveform", WaveFormParameter::Saw),
             osc2_pulsewidth: percentage_param("Osc2 PW", 0.5),
+            pw_keytrack: symmetric_percentage_param("PW Key Track"),
+            osc_sync: percentage_param("Osc Sync", 0.0),
+            osc_spread: FloatParam::new(
+                "Osc Spread",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 50.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit("c")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
             lfo_host_sync: BoolParam::new("Sync", false),
             lfo_key_trig: BoolParam::new("Trig", true),
+            lfo_chord_trig: BoolParam::new("Chord Trig", false),
             lfo_freq: FloatParam::new(
                 "LFO Freq",
                 2.0,
@@ -235,9 +1079,20 @@ impl SynthParams {
             )
             .with_unit("Hz")
             .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            lfo_sync_division: EnumParam::new("LFO Sync Rate", NoteDivision::D4),
+            lfo_sync_offset: percentage_param("LFO Sync Offset", 0.0),
+            lfo_sync_blend: percentage_param("LFO Sync Blend", 0.0),
             lfo_waveform: EnumParam::new("LFO Waveform", LfoWaveFormParameter::Sine),
+            lfo_shape: symmetric_percentage_param("LFO Shape"),
             lfo_filter_mod_depth: symmetric_percentage_param("LFO Filter Mod Depth"),
+            lfo_cos_filter_mod_depth: symmetric_percentage_param("LFO Cos Filter Mod Depth"),
+            lfo_cos_pan_depth: symmetric_percentage_param("LFO Cos Pan Depth"),
             lfo_osc1_detune_mod_depth: symmetric_percentage_param("LFO OSC1 Detune Mod Depth"),
+            lfo_osc2_detune_mod_depth: symmetric_percentage_param("LFO OSC2 Detune Mod Depth"),
+            lfo_unison_detune_depth: symmetric_percentage_param("LFO Unison Detune Depth"),
+            pitch_mod_quantize: BoolParam::new("Pitch Mod Quantize", false),
+            pitch_mod_scale: EnumParam::new("Pitch Mod Scale", Scale::Major),
+            velocity_to_lfo: percentage_param("Vel to LFO", 0.0),
             unison_voices: IntParam::new("Unison Voices", 1, IntRange::Linear { min: 1, max: 7 }),
             unison_detune: FloatParam::new(
                 "Unison Detune",
@@ -250,7 +1105,14 @@ impl SynthParams {
             )
             .with_unit("c")
             .with_value_to_string(formatters::v2s_f32_percentage(1)),
+            velocity_to_unison_detune: percentage_param("Vel to Unison Detune", 0.0),
+            unison_detune_mode: EnumParam::new("Unison Detune Mode", UnisonDetuneMode::Musical),
             unison_stereo_spread: percentage_param("Unison Stereo Spread", 0.5),
+            cheap_stereo: BoolParam::new("Cheap Stereo", false),
+            unison_level_spread: percentage_param("Unison Level Spread", 0.0),
+            note_pan_random: percentage_param("Note Pan Random", 0.0),
+            bend_range_up: bend_range_param("Bend Up"),
+            bend_range_down: bend_range_param("Bend Down"),
             poly_mode: BoolParam::new("Poly", true),
             portamento: FloatParam::new(
                 "Portamento",
@@ -264,10 +1126,65 @@ impl SynthParams {
             .with_step_size(0.01)
             .with_unit("ms")
             .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            pitch_env_start: FloatParam::new(
+                "Start Detune",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 50.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit("c")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            pitch_env_depth: FloatParam::new(
+                "Pitch Env Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 48.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" st")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            pitch_env_decay: FloatParam::new(
+                "Pitch Env Decay",
+                0.02,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 0.5,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.001)
+            .with_value_to_string(v2s_f32_ms_then_s(0, 2)),
+            osc_retrig_legato: BoolParam::new("Osc Legato Retrig", false),
+            osc_phase_reset: BoolParam::new("Osc Phase Reset", false),
+            mono_priority: EnumParam::new("Note Priority", MonoPriority::Last),
+            paraphonic: BoolParam::new("Paraphonic", false),
+            latch: BoolParam::new("Latch", false),
+            freeze: BoolParam::new("Freeze", false),
+            same_note_mode: EnumParam::new("Same Note", SameNoteMode::Stack),
+            voice_overflow: EnumParam::new("Voice Overflow", VoiceOverflow::Steal),
+            block_size: EnumParam::new("Block Size", BlockSize::B64).non_automatable(),
+            quality: EnumParam::new("Quality", Quality::High).non_automatable(),
+            antialias: BoolParam::new("Antialias", true).non_automatable(),
+            filter_oversample: EnumParam::new("Filter Oversampling", FilterOversampling::X2)
+                .non_automatable(),
         }
     }
 }
 
+/// Equal power pan law, normalized to unity gain at center
+fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+    (
+        angle.cos() * std::f32::consts::SQRT_2,
+        angle.sin() * std::f32::consts::SQRT_2,
+    )
+}
+
 fn percentage_param(name: impl Into<String>, default: f32) -> FloatParam {
     FloatParam::new(name, default, FloatRange::Linear { min: 0.0, max: 1.0 })
         .with_step_size(0.01)
@@ -275,6 +1192,24 @@ fn percentage_param(name: impl Into<String>, default: f32) -> FloatParam {
         .with_value_to_string(formatters::v2s_f32_percentage(1))
 }
 
+fn smoothing_time_param(name: impl Into<String>, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min: 0.0,
+            max: 0.5,
+            factor: FloatRange::skew_factor(-2.0),
+        },
+    )
+    .with_step_size(0.001)
+    .with_value_to_string(v2s_f32_ms_then_s(0, 2))
+}
+
+fn bend_range_param(name: impl Into<String>) -> IntParam {
+    IntParam::new(name, 2, IntRange::Linear { min: 0, max: 24 }).with_unit(" st")
+}
+
 fn symmetric_percentage_param(name: impl Into<String>) -> FloatParam {
     FloatParam::new(
         name,
@@ -290,17 +1225,22 @@ fn symmetric_percentage_param(name: impl Into<String>) -> FloatParam {
 }
 
 fn env_time_param(name: impl Into<String>, env_chg: Arc<AtomicU16>) -> FloatParam {
+    env_time_param_from(name, 0.001, env_chg)
+}
+
+/// Envelope time down to `min` seconds. Below a millisecond the value is shown with a decimal.
+fn env_time_param_from(name: impl Into<String>, min: f32, env_chg: Arc<AtomicU16>) -> FloatParam {
     FloatParam::new(
         name,
         0.2,
         FloatRange::Skewed {
-            min: 0.001,
+            min,
             max: 20.0,
             factor: FloatRange::skew_factor(-2.0),
         },
     )
-    .with_step_size(0.001)
-    .with_value_to_string(v2s_f32_ms_then_s(0, 2))
+    .with_step_size(min)
+    .with_value_to_string(v2s_f32_ms_then_s(if min < 0.001 { 1 } else { 0 }, 2))
     .with_callback({
         // Set all voices as needing to update the envelope coefficients.
         let env_chg = env_chg.clone();
@@ -321,24 +1261,75 @@ pub fn v2s_f32_ms_then_s(
     })
 }
 
+/// Key tracking shown as percentage and the resulting cutoff change, e.g. "100%: +12 st/oct".
+/// Cutoff tracks the note 1:1 in semitones at 100%.
+pub fn v2s_f32_keytrack() -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| format!("{:.0}%: {:+.1} st/oct", value * 100.0, value * 12.0))
+}
+
+/// A4 frequency along with the offset in cents from A440
+pub fn v2s_f32_tuning() -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        format!(
+            "{:.2} Hz: {:+.1} ct",
+            value,
+            1200.0 * (value / 440.0).log2()
+        )
+    })
+}
+
+/// Gain as dB, where zero gain is shown as "-inf"
+pub fn v2s_f32_gain_to_db_or_inf(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        if value <= 0.0 {
+            String::from("-inf")
+        } else {
+            format!("{:.digits$}", util::gain_to_db(value))
+        }
+    })
+}
+
+pub fn s2v_f32_gain_to_db_or_inf() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
+    Arc::new(|string| {
+        let string = string.trim().trim_end_matches("dB").trim();
+        if string.eq_ignore_ascii_case("-inf") {
+            Some(0.0)
+        } else {
+            string.parse().ok().map(util::db_to_gain)
+        }
+    })
+}
+
 fn env_gain_param(name: impl Into<String>, env_chg: Arc<AtomicU16>) -> FloatParam {
     FloatParam::new(
         name,
         util::db_to_gain(0.0),
-        // Because we're representing gain as decibels the range is already logarithmic
-        FloatRange::Linear {
-            min: util::db_to_gain(-100.0),
+        // Down to true silence, skewed so the knob travel is roughly even in dB
+        FloatRange::Skewed {
+            min: 0.0,
             max: util::db_to_gain(0.0),
+            factor: FloatRange::gain_skew_factor(-60.0, 0.0),
         },
     )
     .with_unit("dB")
-    .with_value_to_string(formatters::v2s_f32_gain_to_db(1))
+    .with_value_to_string(v2s_f32_gain_to_db_or_inf(1))
+    .with_string_to_value(s2v_f32_gain_to_db_or_inf())
     .with_callback({
         let env_chg = env_chg.clone();
         Arc::new(move |_| env_chg.store(0xFFFF, std::sync::atomic::Ordering::Relaxed))
     })
 }
 
+fn env_division_param(
+    name: impl Into<String>,
+    default: NoteDivision,
+    env_chg: Arc<AtomicU16>,
+) -> EnumParam<NoteDivision> {
+    EnumParam::new(name, default).with_callback(Arc::new(move |_| {
+        env_chg.store(u16::MAX, std::sync::atomic::Ordering::Relaxed)
+    }))
+}
+
 fn freq_param(name: impl Into<String>, default: f32) -> FloatParam {
     FloatParam::new(
         name,
@@ -395,7 +1386,7 @@ impl Plugin for Synth {
     const DEFAULT_INPUT_CHANNELS: u32 = 0;
     const DEFAULT_OUTPUT_CHANNELS: u32 = 2;
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type BackgroundTask = ();
@@ -404,24 +1395,56 @@ impl Plugin for Synth {
         self.params.clone()
     }
 
+    /// Stereo, or mono with the voices summed to the single channel.
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        config.num_input_channels == 0
+            && (config.num_output_channels == 1 || config.num_output_channels == 2)
+    }
+
     fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
         create_editor(self.params.clone(), self.ui_state.clone())
     }
 
+    // Voices and delay lines are allocated here rather than when the plugin is created, which is
+    // off the audio thread and only for instances that are actually going to play
     fn initialize(
         &mut self,
         _bus_config: &BusConfig,
         buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        midi::init_tables();
+        self.reverb = Reverb::new(buffer_config.sample_rate);
+        self.freeze = Freeze::new(buffer_config.sample_rate);
+        self.load_program_snapshots();
+        // Start from the persisted controller positions rather than gliding to them
+        self.controllers
+            .pitch_bend
+            .reset(self.params.pitch_bend.load(Ordering::Relaxed));
+        self.controllers
+            .mod_wheel
+            .reset(self.params.mod_wheel.load(Ordering::Relaxed));
+        self.controllers
+            .channel_pressure
+            .reset(self.params.channel_pressure.load(Ordering::Relaxed));
+        self.param_smoothers.reset(&self.params);
         self.voices = (0..NUM_VOICES)
             .map(|i| Voice::new(i as i32, buffer_config.sample_rate, &self.env_chg))
             .collect();
+        self.draw_filter_drift();
         true
     }
 
     fn reset(&mut self) {
-        self.prng = create_rng();
+        self.reseed();
+        self.reverb.clear();
+        self.widener.clear();
+        self.widener_mix = 0.0;
+        self.master_cut.clear();
+        self.freeze.clear();
+        self.tremolo_phase = 0.0;
+        self.param_smoothers.reset(&self.params);
     }
 
     fn process(
@@ -435,20 +1458,57 @@ impl Plugin for Synth {
             .unwrap()
             .as_nanos() as f64;
 
+        // Turning latch off releases everything it was holding
+        let latch = self.params.latch.value();
+        if self.latch_on && !latch {
+            self.release_latched();
+        }
+        self.latch_on = latch;
+        self.freeze.set_frozen(self.params.freeze.value());
+
+        // Don't let an old tail play out when the reverb is switched back on
+        let reverb_on = self.params.reverb_on.value();
+        if reverb_on && !self.reverb_on {
+            self.reverb.clear();
+        }
+        self.reverb_on = reverb_on;
+
         // NIH-plug has a block-splitting adapter for `Buffer`. While this works great for effect
-        // plugins, for polyphonic synths the block size should be `min(MAX_BLOCK_SIZE,
+        // plugins, for polyphonic synths the block size should be `min(block_size,
         // num_remaining_samples, next_event_idx - block_start_idx)`. Because blocks also need to be
         // split on note events, it's easier to work with raw audio here and to do the splitting by
         // hand.
         let num_samples = buffer.samples();
         //let sample_rate = context.transport().sample_rate;
         let output = buffer.as_slice();
+        let stereo = output.len() > 1; // Otherwise a mono bus
+        let right_channel = output.len() - 1; // Same as the left channel on a mono bus
+
+        let block_size = self.params.block_size.value().samples();
 
+        // Synced envelope times follow the host tempo
+        let transport = context.transport();
+        let tempo = transport.tempo.unwrap_or(DEFAULT_TEMPO);
+        // Song position for the synced LFO phase, only while the host is playing
+        let start_beat = if transport.playing {
+            transport.pos_beats()
+        } else {
+            None
+        };
+        if tempo != self.tempo {
+            self.tempo = tempo;
+            if self.params.env_sync.value() {
+                self.env_chg.store(u16::MAX, Ordering::Relaxed);
+            }
+        }
         let mut next_event = context.next_event();
+        let block_clock = self.sample_clock;
+        let mut osc_peaks = (0.0f32, 0.0f32); // Summed over voices, highest of the blocks
         let mut block_start: usize = 0;
-        let mut block_end: usize = MAX_BLOCK_SIZE.min(num_samples);
+        let mut block_end: usize = block_size.min(num_samples);
 
         while block_start < num_samples {
+            self.sample_clock = block_clock + block_start as u64;
             // First of all, handle all note events that happen at the start of the block, and cut
             // the block short if another event happens before the end of it. To handle polyphonic
             // modulation for new notes properly, we'll keep track of the next internal note index
@@ -477,6 +1537,61 @@ impl Plugin for Synth {
                             } => {
                                 self.note_off(note);
                             }
+                            NoteEvent::MidiPitchBend {
+                                timing: _,
+                                channel: _,
+                                value,
+                            } => {
+                                let bend = (value - 0.5) * 2.0;
+                                self.params.pitch_bend.store(bend, Ordering::Relaxed);
+                                self.controllers.pitch_bend.set_target(
+                                    bend,
+                                    self.params.bend_smoothing.value(),
+                                    self.sample_rate,
+                                );
+                            }
+                            NoteEvent::MidiCC {
+                                timing: _,
+                                channel: _,
+                                cc: MOD_WHEEL_CC,
+                                value,
+                            } => {
+                                self.params.mod_wheel.store(value, Ordering::Relaxed);
+                                self.controllers.mod_wheel.set_target(
+                                    value,
+                                    self.params.mod_wheel_smoothing.value(),
+                                    self.sample_rate,
+                                );
+                            }
+                            NoteEvent::MidiCC {
+                                timing: _,
+                                channel: _,
+                                cc: SUSTAIN_CC,
+                                value,
+                            } => self.controllers.sustain.set_target(
+                                value,
+                                self.params.sustain_smoothing.value(),
+                                self.sample_rate,
+                            ),
+                            NoteEvent::MidiChannelPressure {
+                                timing: _,
+                                channel: _,
+                                pressure,
+                            } => {
+                                self.params
+                                    .channel_pressure
+                                    .store(pressure, Ordering::Relaxed);
+                                self.controllers.channel_pressure.set_target(
+                                    pressure,
+                                    self.params.pressure_smoothing.value(),
+                                    self.sample_rate,
+                                );
+                            }
+                            NoteEvent::MidiProgramChange {
+                                timing: _,
+                                channel: _,
+                                program,
+                            } => self.program_change(program),
                             _ => (),
                         };
 
@@ -493,79 +1608,483 @@ impl Plugin for Synth {
             }
 
             // Silence!
-            output[0][block_start..block_end].fill(0.0);
-            output[1][block_start..block_end].fill(0.0);
+            for channel in output.iter_mut() {
+                channel[block_start..block_end].fill(0.0);
+            }
 
+            let block_beat = start_beat
+                .map(|beat| beat + block_start as f64 * tempo / 60.0 / self.sample_rate as f64);
+            let mut block_osc_peaks = (0.0f32, 0.0f32);
+            self.param_smoothers.set_targets(&self.params, self.sample_rate);
             for voice in self.voices.iter_mut().filter(|v| v.is_playing()) {
-                voice.generate(self.params.borrow_mut(), output, block_start, block_end);
+                voice.generate(
+                    self.params.borrow_mut(),
+                    &self.controllers,
+                    &self.param_smoothers,
+                    output,
+                    block_start,
+                    block_end,
+                    self.tempo,
+                    block_beat,
+                );
+                block_osc_peaks.0 += voice.osc_peak.0;
+                block_osc_peaks.1 += voice.osc_peak.1;
+            }
+            osc_peaks = (
+                osc_peaks.0.max(block_osc_peaks.0),
+                osc_peaks.1.max(block_osc_peaks.1),
+            );
+            self.controllers.advance(block_end - block_start);
+            self.param_smoothers.advance(block_end - block_start);
+
+            // Record the voices, or mix the frozen loop in under them
+            for i in block_start..block_end {
+                let frozen = self
+                    .freeze
+                    .process((output[0][i], output[right_channel][i]));
+                voice::add_to_output(output, i, frozen);
+            }
+
+            // The widener shifts the phase of both channels, so it crossfades with the dry output
+            // rather than switching at 0, and the output is unchanged once it has faded out. It
+            // starts from silence each time it fades back in.
+            let widen = self.params.mono_widen.value();
+            if stereo && (widen > 0.0 || self.widener_mix > 0.0) {
+                if self.widener_mix == 0.0 {
+                    self.widener.clear();
+                }
+                let fade_step = 1.0 / (WIDENER_FADE_SECONDS * self.sample_rate);
+                for i in block_start..block_end {
+                    self.widener_mix = if widen > 0.0 {
+                        (self.widener_mix + fade_step).min(1.0)
+                    } else {
+                        (self.widener_mix - fade_step).max(0.0)
+                    };
+                    let dry = (output[0][i], output[1][i]);
+                    let wide = self.widener.process(dry.0, dry.1, widen);
+                    output[0][i] = dry.0 + (wide.0 - dry.0) * self.widener_mix;
+                    output[1][i] = dry.1 + (wide.1 - dry.1) * self.widener_mix;
+                }
+            }
+
+            self.master_cut.set_parameters(
+                self.params.master_lowcut.value(),
+                self.params.master_highcut.value(),
+                self.sample_rate,
+            );
+            if !self.master_cut.is_bypassed() {
+                for (channel, samples) in output.iter_mut().enumerate().take(2) {
+                    for sample in &mut samples[block_start..block_end] {
+                        *sample = self.master_cut.process(channel, *sample);
+                    }
+                }
+            }
+
+            if reverb_on {
+                let mix = self.params.reverb_mix.value();
+                self.reverb.set_parameters(
+                    self.params.reverb_size.value(),
+                    self.params.reverb_damping.value(),
+                );
+                for i in block_start..block_end {
+                    let dry = (output[0][i], output[right_channel][i]);
+                    let wet = self.reverb.process(dry.0, dry.1);
+                    let mixed = (dry.0 + (wet.0 - dry.0) * mix, dry.1 + (wet.1 - dry.1) * mix);
+                    output[0][i] = 0.0;
+                    output[right_channel][i] = 0.0;
+                    voice::add_to_output(output, i, mixed);
+                }
+            }
+
+            // Master pan. Skipped at center so the output is unchanged there.
+            let pan = &self.params.master_pan;
+            if stereo && (pan.smoothed.is_smoothing() || pan.value() != 0.0) {
+                for i in block_start..block_end {
+                    let (left, right) = pan_gains(pan.smoothed.next());
+                    output[0][i] *= left;
+                    output[1][i] *= right;
+                }
+            }
+
+            // Master tremolo, loudest at the start of each cycle. Synced to the transport it
+            // restarts on the beat.
+            let tremolo_depth = self.params.master_tremolo_depth.value();
+            if tremolo_depth > 0.0 {
+                let synced = self.params.master_tremolo_sync.value();
+                let division_beats = self.params.master_tremolo_division.value().beats();
+                let freq = if synced {
+                    tempo / 60.0 / division_beats
+                } else {
+                    self.params.master_tremolo_freq.value() as f64
+                };
+                if let Some(beat) = block_beat.filter(|_| synced) {
+                    self.tremolo_phase = (beat / division_beats).rem_euclid(1.0);
+                }
+                let dp = freq / self.sample_rate as f64;
+                for i in block_start..block_end {
+                    let dip = 0.5 - 0.5 * (std::f64::consts::TAU * self.tremolo_phase).cos();
+                    let gain = 1.0 - tremolo_depth * dip as f32;
+                    for channel in output.iter_mut() {
+                        channel[i] *= gain;
+                    }
+                    self.tremolo_phase = (self.tremolo_phase + dp).fract();
+                }
+            }
+
+            // Output polarity
+            if self.params.invert_left.value() {
+                output[0][block_start..block_end]
+                    .iter_mut()
+                    .for_each(|s| *s = -*s);
+            }
+            if stereo && self.params.invert_right.value() {
+                output[1][block_start..block_end]
+                    .iter_mut()
+                    .for_each(|s| *s = -*s);
             }
 
             // And then just keep processing blocks until we've run out of buffer to fill
             block_start = block_end;
-            block_end = (block_start + MAX_BLOCK_SIZE).min(num_samples);
+            block_end = (block_start + block_size).min(num_samples);
         }
 
+        self.sample_clock = block_clock + num_samples as u64;
+        self.update_correlation(output);
+        self.update_osc_meters(osc_peaks, num_samples);
+        self.ui_state.active_voices.store(
+            self.voices.iter().filter(|v| v.is_playing()).count() as u32,
+            Ordering::Relaxed,
+        );
+        let mut active_notes = 0u128;
+        for v in self.voices.iter().filter(|v| v.is_playing()) {
+            active_notes |= 1u128 << (v.target_note & 127);
+            active_notes |= 1u128 << (v.osc2_note.unwrap_or(v.target_note) & 127);
+        }
+        self.ui_state.set_active_notes(active_notes);
+
         ProcessStatus::Normal
     }
 }
 
 impl Synth {
     pub fn note_on(&mut self, note: u8, velocity: u8, time: f64) {
+        if self.latched[note as usize] {
+            // Playing a latched note again releases it
+            self.latched[note as usize] = false;
+            self.release_note(note);
+            return;
+        }
+
         let unison = self.params.unison_voices.value() as usize;
-        let lfo_trig = self.params.lfo_key_trig.value();
-        let mut oldest_playing_voice: usize = 0;
-        let mut oldest_playing_time = f64::MAX;
-        let mut oldest_decaying_voice: Option<usize> = None;
-        let mut oldest_decaying_time = f64::MAX;
+        let key_trig = self.params.lfo_key_trig.value();
+        let chord_lfo_phase = if key_trig && self.params.lfo_chord_trig.value() {
+            self.voices
+                .iter()
+                .find(|v| v.is_held())
+                .map(|v| v.lfo_phase())
+        } else {
+            None
+        };
+        let lfo_trig = key_trig && chord_lfo_phase.is_none();
 
-        let mono = false;
+        let mono = !self.params.poly_mode.value();
 
         let mut phase: [f64; voice::MAX_UNISON] = [0.0; MAX_UNISON];
 
-        // TODO: control whether initial phases are randomized or not.
-        for i in 0..voice::MAX_UNISON {
-            phase[i] = self.prng.gen();
+        let phase_reset = self.params.osc_phase_reset.value();
+        if !phase_reset {
+            for i in 0..voice::MAX_UNISON {
+                phase[i] = self.prng.gen();
+            }
+        }
+
+        // Only draw the level offsets when enabled, so the phases for a given seed stay the same
+        let mut unison_levels = [1.0f32; voice::MAX_UNISON];
+        let level_spread = self.params.unison_level_spread.value();
+        if level_spread > 0.0 {
+            for level in unison_levels.iter_mut() {
+                *level += self.prng.gen_range(-1.0..=1.0) * level_spread;
+            }
         }
 
-        if mono {
-            // Mono: always trig voice 0
-            self.voices[0].note_on(note, velocity, time, unison, lfo_trig, &phase);
+        // Drawn only when enabled too, for the same reason
+        let pan_random = self.params.note_pan_random.value();
+        let pan = if pan_random > 0.0 {
+            self.prng.gen_range(-1.0..=1.0) * pan_random
         } else {
-            for i in 0..NUM_VOICES as usize {
-                if !self.voices[i].is_playing() {
-                    // Found an idle voice. Use that.
-                    self.voices[i].note_on(note, velocity, time, unison, lfo_trig, &phase);
-                    return;
-                } else {
-                    if self.voices[i].amp_envelope.is_decaying()
-                        && self.voices[i].start_time < oldest_decaying_time
-                    {
-                        oldest_decaying_voice = Some(i);
-                        oldest_decaying_time = self.voices[i].start_time;
-                    }
-                    if self.voices[i].start_time < oldest_playing_time {
-                        oldest_playing_voice = i;
-                        oldest_playing_time = self.voices[i].start_time;
-                    }
+            0.0
+        };
+
+        // Analog VCO instability: start a few cents off and settle to pitch.
+        let mut start_detune_semitones: f32 =
+            self.analog_prng.gen_range(-1.0..=1.0) * self.params.pitch_env_start.value() / 100.0;
+        if self.params.quality.value() == Quality::Eco {
+            start_detune_semitones = 0.0;
+        }
+
+        // Mono and paraphonic modes play on the first voice, which only exists after `initialize`
+        if self.voices.is_empty() {
+            return;
+        }
+        if self.params.paraphonic.value() {
+            self.held_notes.retain(|(n, _)| *n != note);
+            self.held_notes.push((note, velocity));
+            if !self.voices[0].amp_envelope.is_gate_on() {
+                self.voices[0].note_on(
+                    note,
+                    velocity,
+                    time,
+                    unison,
+                    lfo_trig,
+                    &phase,
+                    &unison_levels,
+                    start_detune_semitones,
+                );
+                self.voices[0].start_sample = self.sample_clock;
+                self.voices[0].pan = pan;
+                if phase_reset {
+                    self.voices[0].trig_oscillators();
                 }
             }
+            self.assign_paraphonic_notes();
+            return;
+        }
+
+        let v = if mono {
+            self.held_notes.retain(|(n, _)| *n != note);
+            self.held_notes.push((note, velocity));
+            if self.mono_priority_note() != Some((note, velocity)) {
+                // A held note with higher priority keeps sounding
+                return;
+            }
+
+            // Mono: always trig voice 0. A note arriving while it's still held is played legato.
+            if self.voices[0].amp_envelope.is_gate_on() {
+                let osc_retrig = self.params.osc_retrig_legato.value();
+                self.voices[0].legato_note_on(note, velocity, osc_retrig);
+                return;
+            }
+            0
+        } else {
+            let sounding = match self.params.same_note_mode.value() {
+                SameNoteMode::Stack => None,
+                SameNoteMode::Reattack => self
+                    .voices
+                    .iter()
+                    .position(|v| v.is_playing() && v.target_note == note),
+            };
+            match sounding.or_else(|| self.allocate_voice()) {
+                Some(v) => v,
+                None => return,
+            }
+        };
+
+        self.voices[v].note_on(
+            note,
+            velocity,
+            time,
+            unison,
+            lfo_trig,
+            &phase,
+            &unison_levels,
+            start_detune_semitones,
+        );
+        self.voices[v].start_sample = self.sample_clock;
+        self.voices[v].pan = pan;
+        if phase_reset {
+            self.voices[v].trig_oscillators();
+        }
+        if let Some(lfo_phase) = chord_lfo_phase {
+            self.voices[v].set_lfo_phase(lfo_phase);
+        }
+    }
+
+    /// Pick a voice for a new note: an idle voice if there is one, otherwise steal the oldest
+    /// decaying voice if one exists, otherwise the oldest playing voice. Voices younger than
+    /// MIN_STEAL_AGE_SECONDS are only stolen when all voices are that young. None when all voices
+    /// are playing and overflow is set to ignore, or before `initialize` allocates the voices.
+    fn allocate_voice(&self) -> Option<usize> {
+        if let Some(idle) = self.voices.iter().position(|v| !v.is_playing()) {
+            return Some(idle);
+        }
+        if self.params.voice_overflow.value() == VoiceOverflow::Ignore {
+            return None;
         }
 
-        // Steal the oldest decaying voice if one exists. Otherwise the oldest playing voice.
-        match oldest_decaying_voice {
-            Some(v) => self.voices[v].note_on(note, velocity, time, unison, lfo_trig, &phase),
-            None => self.voices[oldest_playing_voice]
-                .note_on(note, velocity, time, unison, lfo_trig, &phase),
+        let min_age = (MIN_STEAL_AGE_SECONDS * self.sample_rate) as u64;
+        let oldest = |decaying_only: bool, protect_young: bool| {
+            self.voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !decaying_only || v.amp_envelope.is_decaying())
+                .filter(|(_, v)| !protect_young || self.sample_clock - v.start_sample >= min_age)
+                .min_by_key(|(_, v)| v.start_sample)
+                .map(|(i, _)| i)
+        };
+        oldest(true, true)
+            .or_else(|| oldest(false, true))
+            .or_else(|| oldest(true, false))
+            .or_else(|| oldest(false, false))
+    }
+
+    /// Phase correlation of the output for the mono compatibility meter. +1 is mono-safe, -1 cancels
+    /// out when summed to mono.
+    fn update_correlation(&self, output: &[&mut [f32]]) {
+        if output.len() < 2 {
+            self.ui_state.correlation.store(1.0, Ordering::Relaxed);
+            return;
+        }
+        let (mut lr, mut ll, mut rr) = (0.0f32, 0.0f32, 0.0f32);
+        for (l, r) in output[0].iter().zip(output[1].iter()) {
+            lr += l * r;
+            ll += l * l;
+            rr += r * r;
+        }
+        // Hold the last reading while silent
+        if ll * rr > 1e-12 {
+            let correlation = (lr / (ll * rr).sqrt()).clamp(-1.0, 1.0);
+            let previous = self.ui_state.correlation.load(Ordering::Relaxed);
+            self.ui_state
+                .correlation
+                .store(previous + (correlation - previous) * 0.2, Ordering::Relaxed);
+        }
+    }
+
+    /// Peak hold meters of the OSC1 and OSC2 levels summed over all voices, falling back over
+    /// OSC_METER_RELEASE_SECONDS.
+    fn update_osc_meters(&self, peaks: (f32, f32), num_samples: usize) {
+        let decay = (-(num_samples as f32) / (OSC_METER_RELEASE_SECONDS * self.sample_rate)).exp();
+        for (meter, peak) in self.ui_state.osc_levels.iter().zip([peaks.0, peaks.1]) {
+            let previous = meter.load(Ordering::Relaxed);
+            meter.store(peak.max(previous * decay), Ordering::Relaxed);
         }
     }
 
     pub fn note_off(&mut self, note: u8) {
-        for i in 0..NUM_VOICES as usize {
-            if self.voices[i].target_note == note {
-                self.voices[i].note_off();
+        if self.voices.is_empty() {
+            return;
+        }
+        if self.params.latch.value() {
+            self.latched[note as usize] = true;
+        } else if self.params.paraphonic.value() {
+            self.held_notes.retain(|(n, _)| *n != note);
+            if self.held_notes.is_empty() {
+                self.voices[0].note_off();
+            } else {
+                self.assign_paraphonic_notes();
+            }
+        } else if !self.params.poly_mode.value() {
+            self.mono_note_off(note);
+        } else {
+            self.held_notes.retain(|(n, _)| *n != note);
+            self.release_note(note);
+        }
+    }
+
+    /// The held key that should sound in mono mode
+    fn mono_priority_note(&self) -> Option<(u8, u8)> {
+        match self.params.mono_priority.value() {
+            MonoPriority::Last => self.held_notes.last().copied(),
+            MonoPriority::Highest => self.held_notes.iter().max_by_key(|(n, _)| *n).copied(),
+            MonoPriority::Lowest => self.held_notes.iter().min_by_key(|(n, _)| *n).copied(),
+        }
+    }
+
+    /// Releasing the sounding key in mono mode falls back to the next held key by priority.
+    fn mono_note_off(&mut self, note: u8) {
+        self.held_notes.retain(|(n, _)| *n != note);
+        if self.voices[0].target_note != note {
+            return;
+        }
+        match self.mono_priority_note() {
+            Some((n, velocity)) if self.voices[0].amp_envelope.is_gate_on() => {
+                let osc_retrig = self.params.osc_retrig_legato.value();
+                self.voices[0].legato_note_on(n, velocity, osc_retrig);
+            }
+            _ => self.release_note(note),
+        }
+    }
+
+    /// Spread the two most recently held keys over the oscillators of voice 0. A single held key
+    /// plays on both.
+    fn assign_paraphonic_notes(&mut self) {
+        let recent = &self.held_notes[self.held_notes.len().saturating_sub(2)..];
+        if let (Some((low, _)), Some((high, _))) = (
+            recent.iter().min_by_key(|(n, _)| *n),
+            recent.iter().max_by_key(|(n, _)| *n),
+        ) {
+            self.voices[0].set_paraphonic_notes(*low, *high);
+        }
+    }
+
+    fn release_latched(&mut self) {
+        for note in 0..128u8 {
+            if self.latched[note as usize] {
+                self.latched[note as usize] = false;
+                self.release_note(note);
             }
         }
     }
+
+    fn release_note(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.target_note == note {
+                voice.note_off();
+            }
+        }
+    }
+
+    /// Resolve the presets of the bank for `program_change`. Allocates, so call this outside of the
+    /// audio thread.
+    fn load_program_snapshots(&self) {
+        let snapshots = self
+            .ui_state
+            .preset_bank
+            .lock()
+            .unwrap()
+            .presets
+            .iter()
+            .map(|preset| {
+                let snapshot = preset.to_snapshot(self.params.as_ref());
+                param_snapshot::resolve_snapshot(self.params.as_ref(), &snapshot)
+            })
+            .collect();
+        *self.ui_state.program_snapshots.lock().unwrap() = snapshots;
+    }
+
+    /// Load a preset from the bank, clamped to the last one, whether or not the editor is open.
+    /// There is no parameter setter on the audio thread, so the parameters are set directly and
+    /// the host isn't told about the change. A program change arriving while the editor is writing
+    /// a preset is dropped rather than waited for.
+    fn program_change(&mut self, program: u8) {
+        let snapshots = match self.ui_state.program_snapshots.try_lock() {
+            Ok(snapshots) => snapshots,
+            Err(_) => return,
+        };
+        if snapshots.is_empty() {
+            return;
+        }
+        let program = (program as usize).min(snapshots.len() - 1);
+        // The snapshots were resolved from `self.params`, which lives as long as the synth
+        unsafe { param_snapshot::apply_resolved_snapshot(&snapshots[program], self.sample_rate) };
+        drop(snapshots);
+        self.program = Some(program as u32);
+        self.ui_state
+            .preset_index
+            .store(program as u32, Ordering::Relaxed);
+    }
+}
+
+impl ClapPlugin for Synth {
+    const CLAP_ID: &'static str = "com.andersforsgren.synja";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("Analog subtractive synthesizer");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::Instrument,
+        ClapFeature::Synthesizer,
+        ClapFeature::Stereo,
+    ];
 }
 
 impl Vst3Plugin for Synth {
@@ -573,4 +2092,112 @@ impl Vst3Plugin for Synth {
     const VST3_CATEGORIES: &'static str = "Instrument|Synth";
 }
 
+nih_export_clap!(Synth);
 nih_export_vst3!(Synth);
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+
+    // Default synth with `configure` applied to its parameters and voices allocated as in
+    // `initialize`
+    fn test_synth(configure: impl FnOnce(&mut SynthParams)) -> Synth {
+        let mut synth = Synth::default();
+        let mut params = SynthParams::new(synth.env_chg.clone());
+        configure(&mut params);
+        synth.params = Arc::new(params);
+        synth.param_smoothers.reset(&synth.params);
+        synth.voices = (0..NUM_VOICES)
+            .map(|i| Voice::new(i as i32, synth.sample_rate, &synth.env_chg))
+            .collect();
+        synth
+    }
+
+    // Run the sounding voices for `samples` samples, discarding the output
+    fn run(synth: &mut Synth, samples: usize) {
+        let (mut left, mut right) = (vec![0.0; samples], vec![0.0; samples]);
+        for block_start in (0..samples).step_by(64) {
+            let block_end = (block_start + 64).min(samples);
+            for voice in synth.voices.iter_mut().filter(|v| v.is_playing()) {
+                voice.generate(
+                    &mut synth.params,
+                    &synth.controllers,
+                    &synth.param_smoothers,
+                    &mut [&mut left[..], &mut right[..]],
+                    block_start,
+                    block_end,
+                    synth.tempo,
+                    None,
+                );
+            }
+        }
+    }
+
+    fn held_lfo_phase(synth: &Synth, note: u8) -> f64 {
+        synth
+            .voices
+            .iter()
+            .find(|v| v.is_held() && v.target_note == note)
+            .unwrap()
+            .lfo_phase()
+    }
+
+    // Program changes load the preset on the audio thread, with or without the editor
+    #[test]
+    fn program_change_loads_the_clamped_preset() {
+        let mut synth = Synth::default();
+        synth.program_change(1);
+        assert_eq!(synth.program(), None);
+
+        synth.load_program_snapshots();
+        let bank = SynthPresetBank::default();
+        let last = bank.presets.len() - 1;
+        synth.program_change(127);
+        assert_eq!(synth.program(), Some(last as u32));
+        let expected = bank.presets[last].to_snapshot(synth.params.as_ref());
+        let loaded = param_snapshot::take_snapshot(synth.params.as_ref());
+        for ((id, loaded), (expected_id, expected)) in loaded.iter().zip(&expected) {
+            assert_eq!(id, expected_id);
+            assert!((loaded - expected).abs() < 1e-4, "{}", id);
+        }
+    }
+
+    // Hosts may send notes before `initialize`, when there are no voices yet to play them
+    #[test]
+    fn notes_before_initialize_are_ignored() {
+        for (poly, paraphonic) in [(true, false), (false, false), (false, true)] {
+            let mut synth = Synth::default();
+            let mut params = SynthParams::new(synth.env_chg.clone());
+            params.poly_mode = BoolParam::new("Poly", poly);
+            params.paraphonic = BoolParam::new("Paraphonic", paraphonic);
+            synth.params = Arc::new(params);
+            assert_eq!(synth.allocate_voice(), None);
+            synth.note_on(60, 100, 0.0);
+            synth.note_off(60);
+            assert!(synth.voices.is_empty());
+        }
+    }
+
+    // A chord played while the last one is still releasing starts its own LFO cycle
+    #[test]
+    fn chord_trig_ignores_release_tails() {
+        let mut synth = test_synth(|params| {
+            params.lfo_chord_trig = BoolParam::new("Chord Trig", true);
+        });
+        synth.note_on(60, 100, 0.0);
+        synth.note_on(64, 100, 0.0);
+        run(&mut synth, 4410);
+        assert!(held_lfo_phase(&synth, 60) > 0.1);
+        assert_eq!(held_lfo_phase(&synth, 64), held_lfo_phase(&synth, 60));
+
+        synth.note_off(60);
+        synth.note_off(64);
+        run(&mut synth, 441);
+        assert!(synth.voices.iter().any(|v| v.is_playing()));
+
+        synth.note_on(67, 100, 0.0);
+        synth.note_on(71, 100, 0.0);
+        assert_eq!(held_lfo_phase(&synth, 67), 0.0);
+        assert_eq!(held_lfo_phase(&synth, 71), 0.0);
+    }
+}
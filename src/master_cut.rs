@@ -0,0 +1,86 @@
+// Gentle low cut and high cut on the master output, one pole (6 dB/oct) each so they only shave
+// off the extremes of the whole patch without the resonance or character of the voice filters.
+
+pub const LOWCUT_MIN: f32 = 20.0;
+pub const HIGHCUT_MAX: f32 = 20000.0;
+
+/// One pole lowpass, y[n] = y[n-1] + a (x[n] - y[n-1])
+#[derive(Default, Clone, Copy)]
+struct OnePole {
+    y: f32,
+}
+
+impl OnePole {
+    fn process(&mut self, input: f32, coefficient: f32) -> f32 {
+        self.y += coefficient * (input - self.y);
+        self.y
+    }
+}
+
+fn coefficient(cutoff: f32, sample_rate: f32) -> f32 {
+    let cutoff = cutoff.min(sample_rate * 0.49);
+    1.0 - (-std::f32::consts::TAU * cutoff / sample_rate).exp()
+}
+
+/// Stereo low cut and high cut. Each filter is switched out at its extreme, so both together
+/// leave the output untouched.
+#[derive(Default)]
+pub struct MasterCut {
+    lowcut: [OnePole; 2],
+    highcut: [OnePole; 2],
+    lowcut_coefficient: Option<f32>,
+    highcut_coefficient: Option<f32>,
+}
+
+impl MasterCut {
+    pub fn set_parameters(&mut self, lowcut: f32, highcut: f32, sample_rate: f32) {
+        self.lowcut_coefficient = (lowcut > LOWCUT_MIN).then(|| coefficient(lowcut, sample_rate));
+        self.highcut_coefficient =
+            (highcut < HIGHCUT_MAX).then(|| coefficient(highcut, sample_rate));
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.lowcut_coefficient.is_none() && self.highcut_coefficient.is_none()
+    }
+
+    /// Filter one sample of `channel` (0 left, 1 right)
+    pub fn process(&mut self, channel: usize, input: f32) -> f32 {
+        // Switched out filters are held where switching them back in carries on from the dry
+        // signal instead of clicking
+        let mut output = input;
+        match self.lowcut_coefficient {
+            Some(a) => output -= self.lowcut[channel].process(output, a),
+            None => self.lowcut[channel].y = 0.0,
+        }
+        match self.highcut_coefficient {
+            Some(a) => output = self.highcut[channel].process(output, a),
+            None => self.highcut[channel].y = output,
+        }
+        output
+    }
+
+    pub fn clear(&mut self) {
+        self.lowcut = Default::default();
+        self.highcut = Default::default();
+    }
+}
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparent_at_extremes_and_lowcut_removes_dc() {
+        let mut cut = MasterCut::default();
+        cut.set_parameters(LOWCUT_MIN, HIGHCUT_MAX, 48000.0);
+        assert!(cut.is_bypassed());
+        assert_eq!(cut.process(0, 0.3), 0.3);
+
+        cut.set_parameters(200.0, HIGHCUT_MAX, 48000.0);
+        let mut output = 1.0;
+        for _ in 0..48000 {
+            output = cut.process(0, 1.0);
+        }
+        assert!(output.abs() < 1e-3);
+    }
+}
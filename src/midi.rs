@@ -8,7 +8,6 @@ const PITCH_TABLE_SIZE: usize = 512;
 const POW2_TABLE_SIZE: usize = 1001;
 
 static PITCH: LazyLock<[f32; PITCH_TABLE_SIZE]> = LazyLock::new(|| {
-    println!("initializing");
     let mut arr = [0.0; PITCH_TABLE_SIZE];
     for i in 0..PITCH_TABLE_SIZE {
         arr[i] = (1.0 / 12.0 * (i as i32 - 256) as f32).exp2();
@@ -24,6 +23,12 @@ static POW2: LazyLock<[f32; POW2_TABLE_SIZE]> = LazyLock::new(|| {
     arr
 });
 
+/// Build the lookup tables now, so the first note on the audio thread doesn't have to.
+pub fn init_tables() {
+    LazyLock::force(&PITCH);
+    LazyLock::force(&POW2);
+}
+
 pub fn midi_pitch_to_freq(pitch: f32) -> f32 {
     let pitch_int = pitch as i32;
     let a: f32 = (pitch - pitch_int as f32) * 1000.0;
@@ -43,9 +48,18 @@ pub fn freq_to_midi_pitch_fast(freq: f32) -> f32 {
     12.0 * fast_math::log2_raw(freq / A4_FREQ) + A4_PITCH
 }
 
+const VELOCITY_CURVE_B: f32 = 0.023937;
+/// Amplitude of velocity 0 on the default curve
+pub const DEFAULT_VELOCITY_FLOOR: f32 = VELOCITY_CURVE_B * VELOCITY_CURVE_B;
+
 pub fn midi_velocity_to_amplitude(velocity: u8) -> f32 {
+    midi_velocity_to_amplitude_with_floor(velocity, DEFAULT_VELOCITY_FLOOR)
+}
+
+/// The same curve, bent so velocity 0 gives `floor` and velocity 127 still gives full amplitude
+pub fn midi_velocity_to_amplitude_with_floor(velocity: u8, floor: f32) -> f32 {
     // https://pdfs.semanticscholar.org/92a7/dc5007d770e0c5a3a637f66ee128ba107a92.pdf
-    let b = 0.023937f32;
+    let b = floor.clamp(0.0, 1.0).sqrt();
     let m = (1.0 - b) / 127.0;
     let v = velocity as f32;
     (m * v + b) * (m * v + b)
@@ -55,6 +69,7 @@ pub fn midi_velocity_to_amplitude(velocity: u8) -> f32 {
 mod tests {
     use super::midi_pitch_to_freq;
     use super::midi_pitch_to_freq_slow;
+    use super::{midi_velocity_to_amplitude, midi_velocity_to_amplitude_with_floor};
     use assert_approx_eq::assert_approx_eq;
 
     #[test]
@@ -88,4 +103,15 @@ mod tests {
     fn midi_pitch_to_freq_interpolated() {
         assert_approx_eq!(midi_pitch_to_freq(70.5), 479.8234, 0.01);
     }
+
+    #[test]
+    fn velocity_floor_sets_the_softest_level() {
+        assert_approx_eq!(midi_velocity_to_amplitude_with_floor(0, 0.1), 0.1, 1e-6);
+        assert_approx_eq!(midi_velocity_to_amplitude_with_floor(127, 0.1), 1.0, 1e-6);
+        assert_approx_eq!(midi_velocity_to_amplitude(0), 0.023937 * 0.023937, 1e-9);
+        assert!(
+            midi_velocity_to_amplitude_with_floor(64, 0.1)
+                > midi_velocity_to_amplitude_with_floor(32, 0.1)
+        );
+    }
 }
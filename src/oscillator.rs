@@ -6,6 +6,10 @@ pub struct Oscillator {
     i_buffer: usize,
     n_init: usize,
     phase: f64,
+    reset_offset: Option<f64>, // Samples since the cycle restarted, if it did on the last sample
+    short_blep: bool,          // Use half the BLEP length to save CPU
+    antialias: bool,           // BLEP correction on the saw and square, off is naive and cheaper
+    phase_shape: f64,          // Phase warp for the LFO waveforms, 0 = unchanged
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -23,6 +27,23 @@ pub enum WaveForm {
     Triangle,
 }
 
+impl WaveForm {
+    /// Gain that brings the waveform to the same RMS level as a saw, ignoring the DC offset of
+    /// narrow pulses. Pulse width is limited to 5..95% so very narrow pulses aren't boosted a lot.
+    pub fn loudness_correction(self, pulse_width: f32) -> f64 {
+        const SAW_RMS: f64 = 0.57735; // 1/sqrt(3)
+        match self {
+            WaveForm::Saw => 1.0,
+            WaveForm::Sine => SAW_RMS * std::f64::consts::SQRT_2,
+            WaveForm::Square => {
+                let w = pulse_width.clamp(0.05, 0.95) as f64;
+                SAW_RMS / (2.0 * (w * (1.0 - w)).sqrt())
+            }
+            _ => 1.0,
+        }
+    }
+}
+
 impl Oscillator {
     pub fn new() -> Self {
         Oscillator {
@@ -30,6 +51,42 @@ impl Oscillator {
             buffer: [0.0f32; BLEPLEN / KTABLE],
             i_buffer: 0,
             n_init: 0,
+            reset_offset: None,
+            short_blep: false,
+            antialias: true,
+            phase_shape: 0.0,
+        }
+    }
+
+    /// Truncate the BLEP correction to half its length. Cheaper, slightly more aliasing.
+    pub fn set_short_blep(&mut self, short_blep: bool) {
+        self.short_blep = short_blep;
+    }
+
+    /// Turn the BLEP correction of the saw and square off, trading aliasing for CPU. Switching
+    /// drops any correction in flight, so none of it plays out later.
+    pub fn set_antialias(&mut self, antialias: bool) {
+        if antialias != self.antialias {
+            self.antialias = antialias;
+            self.buffer.fill(0.0);
+            self.i_buffer = 0;
+            self.n_init = 0;
+        }
+    }
+
+    /// Warp the phase of the sine and LFO waveforms, -1..1. Positive values stay near the start of
+    /// the cycle for longer and then speed through the rest, leaning a sine toward a ramp. 0 leaves
+    /// the waveforms unchanged. Only meant for the LFO, as the warped sine isn't antialiased.
+    pub fn set_phase_shape(&mut self, shape: f64) {
+        self.phase_shape = shape.clamp(-1.0, 1.0);
+    }
+
+    fn shaped_phase(&self) -> f64 {
+        if self.phase_shape == 0.0 {
+            self.phase
+        } else {
+            // 4x slower to 4x faster through the start of the cycle
+            self.phase.clamp(0.0, 1.0).powf(4f64.powf(self.phase_shape))
         }
     }
 
@@ -37,16 +94,27 @@ impl Oscillator {
         self.phase = phase;
     }
 
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
     // Offset: where in the phase the discontinuity occurs. E.g. 0.25 = a quarter of the way into the phase.
     //         this would mean sourcing (in_index) from the blep table at 0.25*KTABLE = the 16th sample.
     fn add_blep(&mut self, offset: f64, amp: f64) {
+        if !self.antialias {
+            return;
+        }
         let mut out_index = self.i_buffer;
         let mut in_index = (KTABLE as f64 * offset).floor() as usize;
 
         // The remainder of the phase e.g. 0.75 phases would mean 48 BLEP samples.
         let frac: f64 = KTABLE as f64 * offset % 1.0;
 
-        let c_blep = (BLEPDATA.len() / KTABLE) - 1;
+        let c_blep = if self.short_blep {
+            BLEPDATA.len() / KTABLE / 2
+        } else {
+            (BLEPDATA.len() / KTABLE) - 1
+        };
 
         // Add
         for _i in 0..self.n_init {
@@ -68,7 +136,7 @@ impl Oscillator {
             in_index += KTABLE;
             out_index += 1;
         }
-        self.n_init = c_blep;
+        self.n_init = self.n_init.max(c_blep);
     }
 
     pub fn generate(
@@ -86,6 +154,11 @@ impl Oscillator {
         let dp = freq / sample_rate as f64;
 
         self.phase += dp;
+        self.reset_offset = if self.phase > 1.0 {
+            Some((self.phase - 1.0) / dp)
+        } else {
+            None
+        };
 
         let wave = match waveform {
             WaveForm::Saw => {
@@ -99,7 +172,7 @@ impl Oscillator {
                 if self.phase > 1.0 {
                     self.phase -= 1.0;
                 }
-                (2.0 * PI * self.phase).sin() as f64 // sine -1..1
+                (2.0 * PI * self.shaped_phase()).sin() as f64 // sine -1..1
             }
             WaveForm::Square => {
                 if self.phase > pulse_width as f64 && self.phase - dp <= pulse_width as f64 {
@@ -118,7 +191,8 @@ impl Oscillator {
                 if self.phase > 1.0 {
                     self.phase -= 1.0;
                 }
-                if self.phase > 0.0 && self.phase <= 0.5 as f64 {
+                let phase = self.shaped_phase();
+                if phase > 0.0 && phase <= 0.5 as f64 {
                     1.0
                 } else {
                     0.0
@@ -128,10 +202,11 @@ impl Oscillator {
                 if self.phase > 1.0 {
                     self.phase -= 1.0;
                 }
-                if self.phase > 0.5 {
-                    (2.0 - 2.0 * self.phase) as f64
+                let phase = self.shaped_phase();
+                if phase > 0.5 {
+                    (2.0 - 2.0 * phase) as f64
                 } else {
-                    2.0 * self.phase as f64
+                    2.0 * phase as f64
                 } // Triangle 0..1
             }
         };
@@ -156,11 +231,178 @@ impl Oscillator {
         }
     }
 
+    /// The LFO waveform a quarter cycle ahead of the last generated sample, through the same phase
+    /// shape. For a sine this is the cosine.
+    pub fn quadrature(&self, waveform: WaveForm) -> f64 {
+        let phase = (self.shaped_phase() + 0.25).rem_euclid(1.0);
+        match waveform {
+            WaveForm::Triangle => {
+                if phase > 0.5 {
+                    3.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 1.0
+                }
+            }
+            WaveForm::UnipolarSquare => {
+                if phase > 0.0 && phase <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            _ => (2.0 * PI * phase).sin(),
+        }
+    }
+
+    /// If the last generated sample started a new cycle, the fraction of a sample since the
+    /// restart. Used as the master for hard sync.
+    pub fn reset_offset(&self) -> Option<f64> {
+        self.reset_offset
+    }
+
+    /// Hard sync: restart the cycle `offset` samples (0..1) before the next sample, as reported
+    /// by the master's `reset_offset()`. Must be called before `generate` for that sample.
+    pub fn sync(
+        &mut self,
+        waveform: WaveForm,
+        offset: f64,
+        freq: f64,
+        pulse_width: f32,
+        sample_rate: f32,
+    ) {
+        if freq <= 0.0 {
+            return;
+        }
+        let dp = freq / sample_rate as f64;
+
+        // Where this oscillator was in its cycle at the moment of the master's restart
+        let phase_at_reset = (self.phase + dp * (1.0 - offset)) % 1.0;
+
+        // Antialias the jump back to the start of the cycle, same sign convention as the wrap
+        match waveform {
+            WaveForm::Saw => self.add_blep(offset, phase_at_reset),
+            WaveForm::Square => {
+                if phase_at_reset > pulse_width as f64 {
+                    self.add_blep(offset, -1.0);
+                }
+            }
+            _ => (),
+        }
+
+        // generate() advances by dp first, landing on the phase reached since the restart.
+        self.phase = dp * offset - dp;
+    }
+
+    /// Restart the cycle at phase 0 and drop any pending BLEP correction, so the output from here
+    /// on is the same whatever the oscillator played before.
     pub fn trig(&mut self) {
         self.phase = 0.0;
+        self.buffer.fill(0.0);
+        self.i_buffer = 0;
+        self.n_init = 0;
+        self.reset_offset = None;
     }
 }
 
 fn lerp(a: f64, b: f64, frac: f64) -> f64 {
     (b - a) * frac + a
 }
+
+#[allow(unused)]
+mod tests {
+    use super::{Oscillator, WaveForm};
+
+    // RMS level around the mean of one second of the oscillator at 110 Hz
+    fn ac_rms(waveform: WaveForm, pulse_width: f32) -> f64 {
+        let mut osc = Oscillator::new();
+        let samples: Vec<f64> = (0..44100)
+            .map(|_| osc.generate(waveform, 110.0, 1.0, pulse_width, 44100.0))
+            .collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        (samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn antialias_off_gives_the_naive_saw() {
+        let mut osc = Oscillator::new();
+        while osc.n_init == 0 {
+            osc.generate(WaveForm::Saw, 441.0, 1.0, 0.5, 44100.0);
+        }
+        // A correction is in flight when switching off, and must not be picked up again later
+        osc.set_antialias(false);
+        for _ in 0..1000 {
+            let sample = osc.generate(WaveForm::Saw, 441.0, 1.0, 0.5, 44100.0);
+            assert!((sample - (2.0 * osc.phase - 1.0)).abs() < 1e-9);
+        }
+        osc.set_antialias(true);
+        assert_eq!(osc.n_init, 0);
+    }
+
+    #[test]
+    fn corrected_waveforms_match_saw_loudness() {
+        let saw = ac_rms(WaveForm::Saw, 0.5);
+        for (waveform, pulse_width) in [
+            (WaveForm::Sine, 0.5),
+            (WaveForm::Square, 0.5),
+            (WaveForm::Square, 0.25),
+            (WaveForm::Square, 0.1),
+        ] {
+            let corrected =
+                ac_rms(waveform, pulse_width) * waveform.loudness_correction(pulse_width);
+            assert!(
+                (corrected / saw - 1.0).abs() < 0.05,
+                "{:?} at {} is {} vs saw {}",
+                waveform,
+                pulse_width,
+                corrected,
+                saw
+            );
+        }
+    }
+
+    #[test]
+    fn trig_gives_the_same_transient_after_any_history() {
+        let transient = |history: usize| {
+            let mut osc = Oscillator::new();
+            for _ in 0..history {
+                osc.generate(WaveForm::Square, 331.0, 1.0, 0.3, 44100.0);
+            }
+            osc.trig();
+            (0..256)
+                .map(|_| osc.generate(WaveForm::Saw, 110.0, 1.0, 0.5, 44100.0))
+                .collect::<Vec<f64>>()
+        };
+        let fresh = transient(0);
+        for history in [1, 17, 66, 133, 1000] {
+            assert_eq!(transient(history), fresh, "After {} samples", history);
+        }
+    }
+
+    #[test]
+    fn quadrature_of_a_sine_lfo_traces_a_circle() {
+        for shape in [0.0, 0.5, -0.5] {
+            let mut lfo = Oscillator::new();
+            lfo.set_phase_shape(shape);
+            for _ in 0..1000 {
+                let sin = lfo.generate(WaveForm::Sine, 7.0, 1.0, 0.5, 1000.0);
+                let cos = lfo.quadrature(WaveForm::Sine);
+                assert!((sin * sin + cos * cos - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn quadrature_leads_the_lfo_by_a_quarter_cycle() {
+        let mut lfo = Oscillator::new();
+        let wave: Vec<(f64, f64)> = (0..400)
+            .map(|_| {
+                let value = lfo.generate(WaveForm::Triangle, 10.0, 1.0, 0.5, 1000.0);
+                (value, lfo.quadrature(WaveForm::Triangle))
+            })
+            .collect();
+        // 100 samples a cycle
+        for i in 0..300 {
+            assert!((wave[i].1 - wave[i + 25].0).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use nih_plug::prelude::Params;
+
+use crate::SynthParams;
+
+/// Description of a single parameter, for external editors and controllers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    pub id: String,
+    pub name: String,
+    pub unit: &'static str,
+    /// Plain value range, as (at normalized 0, at normalized 1)
+    pub range: (f32, f32),
+    /// Number of discrete steps, or None for continuous parameters
+    pub step_count: Option<usize>,
+    pub default_normalized: f32,
+    pub normalized: f32,
+    /// Current value formatted the same way the host shows it, including the unit
+    pub display_value: String,
+}
+
+/// Describe every parameter exposed by `params`, in the order the host sees them.
+pub fn describe_params(params: &dyn Params) -> Vec<ParamInfo> {
+    params
+        .param_map()
+        .into_iter()
+        .map(|(id, ptr, _)| unsafe {
+            let normalized = ptr.unmodulated_normalized_value();
+            ParamInfo {
+                id,
+                name: ptr.name().to_string(),
+                unit: ptr.unit(),
+                range: (ptr.preview_plain(0.0), ptr.preview_plain(1.0)),
+                step_count: ptr.step_count(),
+                default_normalized: ptr.default_normalized_value(),
+                normalized,
+                display_value: ptr.normalized_value_to_string(normalized, true),
+            }
+        })
+        .collect()
+}
+
+impl SynthParams {
+    pub fn describe(&self) -> Vec<ParamInfo> {
+        describe_params(self)
+    }
+}
@@ -0,0 +1,232 @@
+// Smoothing for the parameters the voices read per sample. nih-plug fixes a smoother's time when
+// the parameter is built, so these params have no smoother of their own and glide here instead,
+// over the `param_smoothing` time. Targets are set from `Synth::process` once per block and the
+// glides are read by every voice.
+
+use crate::SynthParams;
+
+#[derive(Default, Clone, Copy)]
+pub enum Style {
+    /// Constant change per sample
+    #[default]
+    Linear,
+    /// Constant ratio per sample, for frequencies. Values must stay above 0.
+    Logarithmic,
+}
+
+/// One parameter, gliding to its last target over a fixed number of samples.
+#[derive(Default)]
+pub struct ParamSmoother {
+    style: Style,
+    value: f32,
+    target: f32,
+    step: f32,         // Added per sample when linear, multiplied when logarithmic
+    steps_left: usize, // 0 when the glide is done
+}
+
+impl ParamSmoother {
+    fn new(style: Style) -> Self {
+        Self {
+            style,
+            ..Self::default()
+        }
+    }
+
+    /// Glide to `target` over `time` seconds. Times under a sample jump straight there. Setting
+    /// the target it is already gliding to leaves the glide as it is.
+    pub fn set_target(&mut self, target: f32, time: f32, sample_rate: f32) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        let samples = (time * sample_rate) as usize;
+        if samples == 0 {
+            self.reset(target);
+            return;
+        }
+        self.steps_left = samples;
+        self.step = match self.style {
+            Style::Linear => (target - self.value) / samples as f32,
+            Style::Logarithmic => (target / self.value).powf(1.0 / samples as f32),
+        };
+    }
+
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+        self.steps_left = 0;
+    }
+
+    /// Fill `block` with the values from now, one per sample, without moving along the glide.
+    pub fn fill(&self, block: &mut [f32]) {
+        let mut value = self.value;
+        for (i, sample) in block.iter_mut().enumerate() {
+            if i >= self.steps_left {
+                *sample = self.target;
+                continue;
+            }
+            *sample = value;
+            value = self.stepped(value);
+        }
+    }
+
+    fn advance(&mut self, samples: usize) {
+        if samples >= self.steps_left {
+            self.reset(self.target);
+            return;
+        }
+        self.value = match self.style {
+            Style::Linear => self.value + self.step * samples as f32,
+            Style::Logarithmic => self.value * self.step.powi(samples as i32),
+        };
+        self.steps_left -= samples;
+    }
+
+    fn stepped(&self, value: f32) -> f32 {
+        match self.style {
+            Style::Linear => value + self.step,
+            Style::Logarithmic => value * self.step,
+        }
+    }
+}
+
+/// The block smoothed parameters, cutoffs gliding in pitch and the rest linearly.
+pub struct ParamSmoothers {
+    pub filter_cutoff: ParamSmoother,
+    pub filter_resonance: ParamSmoother,
+    pub filter2_cutoff: ParamSmoother,
+    pub osc1_pulsewidth: ParamSmoother,
+    pub osc2_pulsewidth: ParamSmoother,
+    pub osc1_level: ParamSmoother,
+    pub osc2_level: ParamSmoother,
+    pub osc1_detune: ParamSmoother,
+    pub osc2_detune: ParamSmoother,
+    pub master_gain: ParamSmoother,
+}
+
+impl Default for ParamSmoothers {
+    fn default() -> Self {
+        Self {
+            filter_cutoff: ParamSmoother::new(Style::Logarithmic),
+            filter_resonance: ParamSmoother::new(Style::Linear),
+            filter2_cutoff: ParamSmoother::new(Style::Logarithmic),
+            osc1_pulsewidth: ParamSmoother::new(Style::Linear),
+            osc2_pulsewidth: ParamSmoother::new(Style::Linear),
+            osc1_level: ParamSmoother::new(Style::Linear),
+            osc2_level: ParamSmoother::new(Style::Linear),
+            osc1_detune: ParamSmoother::new(Style::Linear),
+            osc2_detune: ParamSmoother::new(Style::Linear),
+            master_gain: ParamSmoother::new(Style::Linear),
+        }
+    }
+}
+
+impl ParamSmoothers {
+    fn all(&mut self) -> [&mut ParamSmoother; 10] {
+        [
+            &mut self.filter_cutoff,
+            &mut self.filter_resonance,
+            &mut self.filter2_cutoff,
+            &mut self.osc1_pulsewidth,
+            &mut self.osc2_pulsewidth,
+            &mut self.osc1_level,
+            &mut self.osc2_level,
+            &mut self.osc1_detune,
+            &mut self.osc2_detune,
+            &mut self.master_gain,
+        ]
+    }
+
+    // The parameter values in the order of `all`
+    fn values(params: &SynthParams) -> [f32; 10] {
+        [
+            params.filter_cutoff.value(),
+            params.filter_resonance.value(),
+            params.filter2_cutoff.value(),
+            params.osc1_pulsewidth.value(),
+            params.osc2_pulsewidth.value(),
+            params.osc1_level.value(),
+            params.osc2_level.value(),
+            params.osc1_detune.value(),
+            params.osc2_detune.value(),
+            params.master_gain.value(),
+        ]
+    }
+
+    /// Jump every smoother to its parameter's value.
+    pub fn reset(&mut self, params: &SynthParams) {
+        for (smoother, value) in self.all().into_iter().zip(Self::values(params)) {
+            smoother.reset(value);
+        }
+    }
+
+    /// Glide every smoother to its parameter's current value over the `param_smoothing` time.
+    pub fn set_targets(&mut self, params: &SynthParams, sample_rate: f32) {
+        let time = params.param_smoothing.value();
+        for (smoother, value) in self.all().into_iter().zip(Self::values(params)) {
+            smoother.set_target(value, time, sample_rate);
+        }
+    }
+
+    /// Move every smoother `samples` further along its glide, once every voice has read the block.
+    pub fn advance(&mut self, samples: usize) {
+        for smoother in self.all() {
+            smoother.advance(samples);
+        }
+    }
+}
+
+#[allow(unused)]
+mod tests {
+    use super::*;
+    use crate::{percentage_param, smoothing_time_param};
+    use std::sync::atomic::AtomicU16;
+    use std::sync::Arc;
+
+    #[test]
+    fn linear_glide_reaches_target_after_the_smoothing_time() {
+        let mut smoother = ParamSmoother::new(Style::Linear);
+        smoother.set_target(1.0, 0.01, 1000.0); // 10 samples
+        let mut block = [0.0; 16];
+        smoother.fill(&mut block);
+        assert!((block[5] - 0.5).abs() < 1e-6);
+        assert_eq!(block[10], 1.0);
+
+        smoother.advance(5);
+        smoother.set_target(1.0, 0.01, 1000.0); // Same target, the glide carries on
+        let mut block = [0.0; 1];
+        smoother.fill(&mut block);
+        assert!((block[0] - 0.5).abs() < 1e-6);
+
+        smoother.set_target(0.25, 0.0, 1000.0);
+        smoother.fill(&mut block);
+        assert_eq!(block[0], 0.25);
+    }
+
+    #[test]
+    fn logarithmic_glide_passes_the_geometric_midpoint() {
+        let mut smoother = ParamSmoother::new(Style::Logarithmic);
+        smoother.reset(100.0);
+        smoother.set_target(10000.0, 0.01, 1000.0);
+        smoother.advance(5);
+        let mut block = [0.0; 6];
+        smoother.fill(&mut block);
+        assert!((block[0] - 1000.0).abs() < 0.1);
+        assert_eq!(block[5], 10000.0);
+    }
+
+    #[test]
+    fn set_targets_glide_over_the_param_smoothing_time() {
+        let env_chg = Arc::new(AtomicU16::new(u16::MAX));
+        let mut smoothers = ParamSmoothers::default();
+        smoothers.reset(&SynthParams::new(env_chg.clone())); // Resonance 10%
+        let mut params = SynthParams::new(env_chg);
+        params.filter_resonance = percentage_param("Filter Resonance", 0.5);
+        params.param_smoothing = smoothing_time_param("Param Smoothing", 0.01);
+        smoothers.set_targets(&params, 1000.0);
+        let mut block = [0.0; 16];
+        smoothers.filter_resonance.fill(&mut block);
+        assert!((block[5] - 0.3).abs() < 1e-6);
+        assert_eq!(block[10], 0.5);
+    }
+}
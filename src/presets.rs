@@ -1,78 +1,103 @@
-use std::str::FromStr;
-
+use nih_plug::prelude::Params;
 use serde::{Deserialize, Serialize};
 
-use super::{Param, PARAMS};
+use crate::editor::param_snapshot::ParamSnapshot;
 
-const CURRENT_FORMAT_VERSION: u32 = 1;
+// Version 1 banks were written by the old VST engine
+const CURRENT_FORMAT_VERSION: u32 = 2;
+const DEFAULT_PRESET_JSON: &str = include_str!("default_presets.json");
+const PATCH_STRING_PREFIX: &str = "synja:";
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SynthPreset {
     pub name: String,
-    pub params: Vec<f32>,
+    /// Plain (not normalized) parameter values, by parameter id
+    pub params: Vec<(String, f32)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SynthPresetBank {
+    #[serde(default)]
+    pub version: u32,
     pub presets: Vec<SynthPreset>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SerializedSynthPreset {
-    pub name: String,
-    pub params: Vec<(String, f32)>,
-}
+impl SynthPreset {
+    pub fn from_params(name: impl Into<String>, params: &impl Params) -> Self {
+        let params = params
+            .param_map()
+            .into_iter()
+            .map(|(id, ptr, _)| unsafe {
+                (id, ptr.preview_plain(ptr.unmodulated_normalized_value()))
+            })
+            .collect();
+        SynthPreset {
+            name: name.into(),
+            params,
+        }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SerializedSynthPresetBank {
-    #[serde(default)]
-    pub version: u32,
-    pub presets: Vec<SerializedSynthPreset>,
+    /// The preset as a single line of text, for sharing a patch without a file.
+    pub fn to_patch_string(&self) -> String {
+        let json = serde_json::to_string(self).expect("Preset serialization failed");
+        format!("{}{}", PATCH_STRING_PREFIX, json)
+    }
+
+    /// Parse a patch string made by `to_patch_string`. Unknown parameter ids are kept, and ignored
+    /// when the preset is applied.
+    pub fn from_patch_string(patch: &str) -> Option<Self> {
+        let json = patch.trim().strip_prefix(PATCH_STRING_PREFIX)?;
+        serde_json::from_str(json).ok()
+    }
+
+    /// Normalized values for every parameter. Parameters missing from the preset get their default
+    /// value, so parameters added after the preset was written don't keep their previous setting.
+    pub fn to_snapshot(&self, params: &impl Params) -> ParamSnapshot {
+        params
+            .param_map()
+            .into_iter()
+            .map(|(id, ptr, _)| {
+                let value = match self.params.iter().find(|(param_id, _)| *param_id == id) {
+                    Some((_, plain)) => unsafe { ptr.preview_normalized(*plain) },
+                    None => unsafe { ptr.default_normalized_value() },
+                };
+                (id, value)
+            })
+            .collect()
+    }
 }
 
 impl SynthPresetBank {
-    pub fn from_serialized(data: SerializedSynthPresetBank) -> Self {
-        let mut presets: Vec<SynthPreset> = vec![];
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut bank: SynthPresetBank = serde_json::from_str(json)?;
+        bank.upgrade();
+        Ok(bank)
+    }
 
-        for sp in data.presets {
-            let mut paramvec: Vec<f32> = vec![0.0; PARAMS.len()]; // TODO: should be defaults?
-            for (param_name, val) in sp.params {
-                match Param::from_str(&param_name) {
-                    Ok(par) => {
-                        paramvec[par.index()] = par.get_config().map_to_daw(val as f64);
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Convert presets from older versions to the current parameter ids and values.
+    fn upgrade(&mut self) {
+        if self.version < 2 {
+            for preset in self.presets.iter_mut() {
+                for (id, value) in preset.params.iter_mut() {
+                    match id.as_str() {
+                        "LfoOsc1DetuneDepth" => *id = "LfoOsc1DetuneModDepth".to_owned(),
+                        // Was 1 = Mono, 2 = Poly
+                        "PolyMode" => *value = if *value > 1.5 { 1.0 } else { 0.0 },
+                        _ => (),
                     }
-                    Err(_) => info!("Failed to parse param name {}", param_name),
                 }
             }
-            presets.push(SynthPreset {
-                name: sp.name,
-                params: paramvec,
-            })
         }
-        SynthPresetBank { presets }
+        self.version = CURRENT_FORMAT_VERSION;
     }
+}
 
-    pub fn to_serialized(&self) -> SerializedSynthPresetBank {
-        let mut presets: Vec<SerializedSynthPreset> = vec![];
-        for preset in self.presets.iter() {
-            let mut serialized_params: Vec<(String, f32)> = vec![];
-            for i in 0..PARAMS.len() {
-                let param = Param::from_index(i);
-                let param_name = param.to_string();
-                serialized_params.push((
-                    param_name,
-                    param.get_config().map_to_plugin(preset.params[i]) as f32,
-                ));
-            }
-            let sp = SerializedSynthPreset {
-                name: preset.name.clone(),
-                params: serialized_params,
-            };
-            presets.push(sp);
-        }
-        SerializedSynthPresetBank {
-            version: CURRENT_FORMAT_VERSION,
-            presets,
-        }
+impl Default for SynthPresetBank {
+    fn default() -> Self {
+        SynthPresetBank::from_json(DEFAULT_PRESET_JSON).expect("Invalid default presets")
     }
 }
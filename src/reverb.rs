@@ -0,0 +1,138 @@
+// Freeverb style reverb: eight parallel lowpass feedback combs into four series allpasses, per
+// channel. Delay lengths are the original tunings at 44.1kHz, scaled to the sample rate.
+
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23; // Extra delay on the right channel, decorrelates the tails
+const INPUT_GAIN: f32 = 0.015;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const WET_GAIN: f32 = 3.0;
+
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Comb {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.index] = input + self.filter_store * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+        self.filter_store = 0.0;
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Allpass {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.index];
+        self.buffer[self.index] = input + buffered * ALLPASS_FEEDBACK;
+        self.index = (self.index + 1) % self.buffer.len();
+        buffered - input
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Channel {
+    fn new(sample_rate: f32, spread: usize) -> Self {
+        let scale = |length: usize| ((length + spread) as f32 * sample_rate / 44100.0) as usize;
+        Channel {
+            combs: COMB_TUNING.iter().map(|l| Comb::new(scale(*l))).collect(),
+            allpasses: ALLPASS_TUNING
+                .iter()
+                .map(|l| Allpass::new(scale(*l)))
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut output: f32 = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.process(output);
+        }
+        output
+    }
+}
+
+/// Without delay lines until created with `new`, and silent until then.
+#[derive(Default)]
+pub struct Reverb {
+    channels: (Channel, Channel),
+    feedback: f32,
+    damping: f32,
+}
+
+impl Reverb {
+    /// Allocates the delay lines, so call this outside of the audio thread.
+    pub fn new(sample_rate: f32) -> Self {
+        Reverb {
+            channels: (
+                Channel::new(sample_rate, 0),
+                Channel::new(sample_rate, STEREO_SPREAD),
+            ),
+            feedback: 0.0,
+            damping: 0.0,
+        }
+    }
+
+    /// Room size and damping, both 0..1
+    pub fn set_parameters(&mut self, size: f32, damping: f32) {
+        self.feedback = 0.7 + 0.28 * size.clamp(0.0, 1.0);
+        self.damping = 0.4 * damping.clamp(0.0, 1.0);
+    }
+
+    /// The wet signal only, for a stereo input.
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let input = (left + right) * INPUT_GAIN;
+        (
+            self.channels.0.process(input, self.feedback, self.damping) * WET_GAIN,
+            self.channels.1.process(input, self.feedback, self.damping) * WET_GAIN,
+        )
+    }
+
+    pub fn clear(&mut self) {
+        for channel in [&mut self.channels.0, &mut self.channels.1] {
+            channel.combs.iter_mut().for_each(Comb::clear);
+            channel.allpasses.iter_mut().for_each(Allpass::clear);
+        }
+    }
+}
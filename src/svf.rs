@@ -0,0 +1,81 @@
+use crate::filter::*;
+use std::f64::consts::PI;
+
+// Trapezoidal integrated state variable filter, from
+// https://cytomic.com/files/dsp/SvfLinearTrapOptimised2.pdf
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SvfMode {
+    Lowpass,
+    Bandpass,
+    Highpass,
+}
+
+pub struct StateVariableFilter {
+    mode: SvfMode,
+    ic1eq: f64,
+    ic2eq: f64,
+
+    k: f64,
+    a1: f64,
+    a2: f64,
+    a3: f64,
+    coeff_cutoff: f32,
+    coeff_resonance: f32,
+}
+
+impl StateVariableFilter {
+    pub fn new() -> Self {
+        StateVariableFilter {
+            mode: SvfMode::Lowpass,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+            k: 2.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            coeff_cutoff: -1.0,
+            coeff_resonance: 0.0,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: SvfMode) {
+        self.mode = mode;
+    }
+
+    fn compute_coeffs(&mut self, cutoff: f32, resonance: f32, sample_rate: f32) {
+        if self.coeff_cutoff == cutoff && self.coeff_resonance == resonance {
+            return;
+        }
+
+        let fc = cutoff.clamp(1.0, sample_rate * 0.49) as f64;
+        let g = (PI * fc / sample_rate as f64).tan();
+        // Resonance 0..1 maps to Q 0.5..~25, stopping short of self oscillation
+        self.k = 2.0 - 1.92 * resonance.clamp(0.0, 1.0) as f64;
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+
+        self.coeff_cutoff = cutoff;
+        self.coeff_resonance = resonance;
+    }
+}
+
+impl Filter for StateVariableFilter {
+    fn process(&mut self, in_sample: f32, sample_rate: f32, cutoff: f32, resonance: f32) -> f32 {
+        self.compute_coeffs(cutoff, resonance, sample_rate);
+
+        let v0 = in_sample as f64;
+        let v3 = v0 - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        (match self.mode {
+            SvfMode::Lowpass => v2,
+            SvfMode::Bandpass => v1,
+            SvfMode::Highpass => v0 - self.k * v1 - v2,
+        }) as f32
+    }
+}
@@ -1,16 +1,36 @@
+use crate::controllers::{ControllerValues, Controllers};
 use crate::envelope::*;
 use crate::filter::Filter;
 use crate::huovilainen::HuovilainenMoog;
 use crate::midi::*;
 use crate::oscillator::*;
+use crate::param_smoothing::ParamSmoothers;
+use crate::svf::{StateVariableFilter, SvfMode};
+use crate::widener::Widener;
+use crate::EnvRetrigMode;
+use crate::Filter2Mode;
+use crate::FilterRouting;
+use crate::NoteDivision;
+use crate::OscLevelCurve;
+use crate::Quality;
 use crate::SynthParams;
+use crate::UnisonDetuneMode;
 use crate::MAX_BLOCK_SIZE;
+use nih_plug::prelude::{EnumParam, FloatParam};
 use std::ops::Not;
 use std::sync::atomic::AtomicU16;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 pub const MAX_UNISON: usize = 7;
+const UNISON_DETUNE_MAX_HZ: f32 = 10.0; // Unison detune at 100% in Hz mode
+const START_DETUNE_SETTLE_SECONDS: f32 = 0.02; // Time for the note start detune to settle within 1%
+const LFO_RETRIG_FADE_SECONDS: f32 = 0.005; // LFO smoothing time after a key trig
+const LFO_RETRIG_FADE_TAU_SECONDS: f32 = 0.001;
+const OSC_FADE_SECONDS: f32 = 0.005; // Fade time when switching an oscillator on or off
+const OSC_SILENT_GAIN: f32 = 0.0001; // -80 dB, oscillators below this level are not generated
+const ACCENT_VELOCITY: u8 = 64; // Velocity the accent starts above, reaching full at 127
+const UNISON_FADE_SECONDS: f32 = 0.005; // Fade time for unison voices added or removed mid note
 
 static UNISON_DETUNE_PATTERN: &'static [&[f32]] = &[
     &[],
@@ -38,16 +58,47 @@ pub(crate) struct Voice {
     sample_rate: f32,
     #[allow(dead_code)]
     pub id: i32, // DAW voice identifier
-    pub target_note: u8, // Portamento target note
-    pub note: f32,       // Current note
-    pub bend: f32,       // bend in semitones
+    pub target_note: u8,               // Portamento target note
+    pub osc2_note: Option<u8>,         // Paraphonic note for OSC2, None follows target_note
+    pub note: f32,                     // Current note
+    pub controllers: ControllerValues, // MIDI controller positions at the current sample
+    bend_range: (f32, f32),            // Semitones at full bend (down, up)
+    tuning: f32,                       // Master tuning offset from A440, in semitones
+    velocity_floor: f32,               // Amplitude of the softest velocity
+    pub start_detune: f32,             // Decaying pitch offset at note start, in semitones
+    pitch_env: f32,                    // Velocity pitch envelope, 1 at note start decaying to 0
+    pitch_env_semitones: f32,          // Current pitch envelope offset
     pub velocity: u8,
     pub start_time: f64,
+    pub start_sample: u64, // Synth sample clock at note on
+    pub filter_drift: f32, // Fixed cutoff offset of this voice, -1..1 times the filter drift
+    pub pan: f32,          // Note pan position, -1..1, on top of the unison spread
     pub unison: usize,
+    unison_levels: [f32; MAX_UNISON], // Per unison voice gain, randomized at note on
+    unison_fade: Option<[f32; MAX_UNISON]>, // Unison voice count fade, None snaps to the count
+    unison_pattern: [(f32, f32); MAX_UNISON], // Detune and stereo spread position per unison voice
     pub osc1: Vec<Oscillator>,
     pub osc2: Vec<Oscillator>,
+    pub osc2_free: Vec<Oscillator>, // Free running OSC2 for crossfading with the synced one
+    osc1_octave: Vec<Oscillator>,   // OSC1 an octave up, synced to OSC1 so it stays in phase
     pub lfo: Oscillator,
+    lfo_value: f32,                        // LFO output after the retrigger fade
+    lfo_cos_value: f32,                    // LFO quadrature output after the retrigger fade
+    gate_samples: u32,                     // Samples since note on, saturating
+    min_gate_samples: u32,                 // Shortest gate before a note off is honored
+    pending_note_off: bool,                // Note off arrived before the minimum gate time
+    lfo_fade_samples: u32,                 // Remaining samples of smoothing after an LFO retrigger
+    osc_fade: Option<(f32, f32)>, // OSC1/OSC2 on/off fade, None snaps to the current setting
+    frozen_cutoff_mod: Option<f32>, // Cutoff modulation in semitones held by filter freeze
+    filter_env_retrig_mode: EnvRetrigMode, // As of the last generate, only matters while sounding
+    pub osc_peak: (f32, f32), // OSC1/OSC2 peak level after the amp envelope, in the last generate
+    pub cutoff: f32,          // Modulated filter cutoff in Hz, as of the last generated sample
     pub filter: (HuovilainenMoog, HuovilainenMoog),
+    pub filter_series: (HuovilainenMoog, HuovilainenMoog), // Second stage when routing is series
+    pub filter2: (StateVariableFilter, StateVariableFilter), // Parallel second filter
+    warmth_dc: (DcBlocker, DcBlocker), // Removes the DC offset of the asymmetric warmth
+    widener: Widener,                  // Cheap stereo and mono filter width
+    widener_on: bool,                  // Widener state as of the last generate
     pub env_change: Arc<AtomicU16>,
     pub amp_envelope: AdsrEnvelope,
     pub filter_envelope: AdsrEnvelope,
@@ -59,21 +110,53 @@ impl Voice {
             sample_rate,
             id,
             target_note: 0,
+            osc2_note: None,
             note: 0.0,
-            bend: 0.0,
+            controllers: ControllerValues::default(),
+            bend_range: (2.0, 2.0),
+            tuning: 0.0,
+            velocity_floor: DEFAULT_VELOCITY_FLOOR,
+            start_detune: 0.0,
+            pitch_env: 0.0,
+            pitch_env_semitones: 0.0,
             velocity: 0,
             start_time: 0.0,
+            start_sample: 0,
+            pan: 0.0,
+            filter_drift: 0.0,
             unison: 1,
+            unison_levels: [1.0; MAX_UNISON],
+            unison_fade: None,
+            unison_pattern: [(0.0, 0.0); MAX_UNISON],
             osc1: (0..MAX_UNISON).map(|_| Oscillator::new()).collect(),
             osc2: (0..MAX_UNISON).map(|_| Oscillator::new()).collect(),
+            osc2_free: (0..MAX_UNISON).map(|_| Oscillator::new()).collect(),
+            osc1_octave: (0..MAX_UNISON).map(|_| Oscillator::new()).collect(),
             lfo: Oscillator::new(),
+            lfo_value: 0.0,
+            lfo_cos_value: 0.0,
+            lfo_fade_samples: 0,
+            gate_samples: 0,
+            min_gate_samples: 0,
+            pending_note_off: false,
+            osc_fade: None,
+            frozen_cutoff_mod: None,
+            filter_env_retrig_mode: EnvRetrigMode::Continue,
+            osc_peak: (0.0, 0.0),
+            cutoff: 0.0,
             filter: (HuovilainenMoog::new(), HuovilainenMoog::new()),
+            filter_series: (HuovilainenMoog::new(), HuovilainenMoog::new()),
+            filter2: (StateVariableFilter::new(), StateVariableFilter::new()),
+            warmth_dc: (DcBlocker::default(), DcBlocker::default()),
+            widener: Widener::default(),
+            widener_on: false,
             env_change: env_chg.clone(),
             amp_envelope: AdsrEnvelope::new(id),
             filter_envelope: AdsrEnvelope::new(id),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn note_on(
         &mut self,
         note: u8,
@@ -82,22 +165,85 @@ impl Voice {
         unison: usize,
         lfo_trig: bool,
         start_phases: &[f64; MAX_UNISON],
+        unison_levels: &[f32; MAX_UNISON],
+        start_detune: f32,
     ) {
         for i in 0..MAX_UNISON {
             self.osc1[i].set_phase(start_phases[i]);
+            self.osc1_octave[i].set_phase((2.0 * start_phases[i]).fract());
+        }
+        self.unison_levels = *unison_levels;
+        if !self.is_playing() {
+            // No fade in for oscillators that are already on when a new note starts
+            self.osc_fade = None;
+            self.unison_fade = None;
+            self.frozen_cutoff_mod = None;
         }
         self.target_note = note;
+        self.osc2_note = None;
+        self.start_detune = start_detune;
+        self.pitch_env = 1.0;
         if lfo_trig {
             self.lfo.trig();
+            self.lfo_fade_samples = (LFO_RETRIG_FADE_SECONDS * self.sample_rate) as u32;
         }
         self.unison = unison;
         self.velocity = velocity;
         self.start_time = time;
+        self.gate_samples = 0;
+        self.pending_note_off = false;
+        // The velocity scaled sustain level is per note, so refresh this voice's envelope
+        self.env_change
+            .fetch_or(1u16 << (self.id as u16), Ordering::Relaxed);
         self.amp_envelope.gate_on();
-        self.filter_envelope.gate_on();
+        match self.filter_env_retrig_mode {
+            EnvRetrigMode::Reset => self.filter_envelope.gate_on_from_zero(),
+            EnvRetrigMode::Continue => self.filter_envelope.gate_on(),
+        }
+    }
+
+    pub fn lfo_phase(&self) -> f64 {
+        self.lfo.phase()
+    }
+
+    /// Jump the LFO to `phase`, gliding there like a retrigger
+    pub fn set_lfo_phase(&mut self, phase: f64) {
+        self.lfo.set_phase(phase);
+        self.lfo_fade_samples = (LFO_RETRIG_FADE_SECONDS * self.sample_rate) as u32;
+    }
+
+    /// Mono legato: glide to the new note without retriggering the envelopes.
+    pub fn legato_note_on(&mut self, note: u8, velocity: u8, osc_retrig: bool) {
+        if osc_retrig {
+            self.trig_oscillators();
+        }
+        self.target_note = note;
+        self.velocity = velocity;
+        self.pending_note_off = false;
+    }
+
+    /// Restart every oscillator at phase 0 with no BLEP correction left from the previous note
+    pub fn trig_oscillators(&mut self) {
+        for i in 0..MAX_UNISON {
+            self.osc1[i].trig();
+            self.osc1_octave[i].trig();
+            self.osc2[i].trig();
+            self.osc2_free[i].trig();
+        }
+    }
+
+    /// Paraphonic mode: OSC1 glides to `osc1_note` and OSC2 plays `osc2_note` at the same offset.
+    pub fn set_paraphonic_notes(&mut self, osc1_note: u8, osc2_note: u8) {
+        self.target_note = osc1_note;
+        self.osc2_note = Some(osc2_note);
     }
 
     pub fn note_off(&mut self) {
+        if self.gate_samples < self.min_gate_samples && self.amp_envelope.is_gate_on() {
+            // Released by generate once the minimum time has passed
+            self.pending_note_off = true;
+            return;
+        }
         self.amp_envelope.gate_off();
         self.filter_envelope.gate_off();
     }
@@ -106,6 +252,11 @@ impl Voice {
         !self.amp_envelope.is_idle()
     }
 
+    /// Key still down, the note isn't in its release or waiting out the minimum note length
+    pub fn is_held(&self) -> bool {
+        self.amp_envelope.is_gate_on() && !self.pending_note_off
+    }
+
     fn get_oscillator_semitone(&mut self, detune: f32, portamento: f32) -> f32 {
         if portamento <= 0.0 {
             self.note = self.target_note as f32;
@@ -113,41 +264,182 @@ impl Voice {
             self.note += (self.target_note as f32 - self.note) * 1.0 / (100.0 * portamento);
         }
 
-        self.note + self.bend as f32 + detune
+        let bend = self.controllers.pitch_bend;
+        let bend_range = if bend < 0.0 {
+            self.bend_range.0
+        } else {
+            self.bend_range.1
+        };
+        self.note
+            + bend * bend_range
+            + self.tuning
+            + self.start_detune
+            + self.pitch_env_semitones
+            + detune
     }
 
-    fn frequency(&mut self, detune_semitones: f32, octave: i32, portamento: f32) -> f32 {
+    fn frequency(
+        &mut self,
+        detune_semitones: f32,
+        detune_hz: f32,
+        octave: i32,
+        portamento: f32,
+    ) -> f32 {
         // Requires +2 offset                -2    -1    0    1    2
         const OCTIAVE_MULTIPLIER: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
         let octave_multiplier = OCTIAVE_MULTIPLIER[octave as usize + 2];
 
         let semitone = self.get_oscillator_semitone(detune_semitones, portamento);
 
-        midi_pitch_to_freq(semitone) * octave_multiplier
+        midi_pitch_to_freq(semitone) * octave_multiplier + detune_hz
     }
 
     // Note amplitude from midi velocity
     fn note_amplitude(&self) -> f64 {
-        midi_velocity_to_amplitude(self.velocity) as f64
+        midi_velocity_to_amplitude_with_floor(self.velocity, self.velocity_floor) as f64
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn generate(
         &mut self,
         params: &mut Arc<SynthParams>,
+        controllers: &Controllers,
+        param_smoothers: &ParamSmoothers,
         output: &mut [&mut [f32]],
         block_start: usize,
         block_end: usize,
+        tempo: f64,
+        beat: Option<f64>, // Host song position in quarter notes at block_start, while playing
     ) {
         let osc1_waveform: WaveForm = params.osc1_waveform.value().into();
         let osc2_waveform: WaveForm = params.osc2_waveform.value().into();
+        let osc_level_normalize = params.osc_level_normalize.value();
+        let osc_level_curve = params.osc_level_curve.value();
+        let osc1_octave_mix = params.osc1_octave_mix.value();
+        self.filter_env_retrig_mode = params.filter_env_retrig_mode.value();
+        self.osc_peak = (0.0, 0.0);
         let lfo_waveform: WaveForm = params.lfo_waveform.value().into();
+        self.lfo.set_phase_shape(params.lfo_shape.value() as f64);
+        let mut lfo_freq = params.lfo_freq.value() as f64;
+        if params.lfo_host_sync.value() {
+            let division_beats = params.lfo_sync_division.value().beats();
+            let synced_freq = tempo / 60.0 / division_beats;
+            let blend = params.lfo_sync_blend.value() as f64;
+            // Locked to the transport, so every voice is in phase with the beat
+            if let Some(beat) = beat.filter(|_| blend == 0.0) {
+                let offset = params.lfo_sync_offset.value() as f64;
+                self.lfo
+                    .set_phase((beat / division_beats + offset).rem_euclid(1.0));
+            }
+            lfo_freq = synced_freq + (lfo_freq - synced_freq) * blend;
+        }
+        let lfo_fade_coeff = 1.0 - (-1.0 / (LFO_RETRIG_FADE_TAU_SECONDS * self.sample_rate)).exp();
+        let filter_routing = params.filter_routing.value();
+        let unison_detune_mode = params.unison_detune_mode.value();
 
-        self.bend = 0.0; // states[STATE_BEND].get(); // TODO: Add pitch bend after switch to nih
+        let eco = params.quality.value() == Quality::Eco;
+        let filter_oversampling = if eco {
+            1
+        } else {
+            params.filter_oversample.value().factor()
+        };
+        let res_comp = params.res_comp.value();
+        let res_tune_comp = params.res_tune_comp.value();
+        let filter2_mode = match params.filter2_mode.value() {
+            Filter2Mode::Off => None,
+            Filter2Mode::Lowpass => Some(SvfMode::Lowpass),
+            Filter2Mode::Bandpass => Some(SvfMode::Bandpass),
+            Filter2Mode::Highpass => Some(SvfMode::Highpass),
+        };
+        if let Some(mode) = filter2_mode {
+            self.filter2.0.set_mode(mode);
+            self.filter2.1.set_mode(mode);
+        }
+        let filter_blend: f32 = params.filter_blend.value();
+        let cutoff_floor: f32 = params.filter_cutoff_floor.value();
+        let filter_drift = if eco {
+            0.0
+        } else {
+            self.filter_drift * params.filter_drift.value()
+        };
+        let filter_freeze = params.filter_freeze.value();
+        if !filter_freeze {
+            self.frozen_cutoff_mod = None;
+        }
+        let mono_filter = params.mono_filter.value();
+        let voice_softclip = params.voice_softclip.value();
+        let pan = (self.pan != 0.0).then(|| crate::pan_gains(self.pan));
+        let filter_bypass = (
+            params.osc1_filter_bypass.value(),
+            params.osc2_filter_bypass.value(),
+        );
+        let osc_solo = (params.osc1_solo.value(), params.osc2_solo.value());
+        let osc_on = if osc_solo.0 || osc_solo.1 {
+            osc_solo
+        } else {
+            (params.osc1_on.value(), params.osc2_on.value())
+        };
+        let osc_fade_step = 1.0 / (OSC_FADE_SECONDS * self.sample_rate);
+        let mut osc_fade = self.osc_fade.unwrap_or((
+            if osc_on.0 { 1.0 } else { 0.0 },
+            if osc_on.1 { 1.0 } else { 0.0 },
+        ));
+        // Harder notes get more LFO modulation
+        let velocity_lfo_scale = velocity_scale(1.0, self.velocity, params.velocity_to_lfo.value());
+        for filter in [
+            &mut self.filter.0,
+            &mut self.filter.1,
+            &mut self.filter_series.0,
+            &mut self.filter_series.1,
+        ] {
+            filter.set_oversampling(filter_oversampling);
+            filter.set_resonance_compensation(res_comp);
+            filter.set_cutoff_compensation(res_tune_comp);
+        }
+        let antialias = params.antialias.value();
+        for osc in self
+            .osc1
+            .iter_mut()
+            .chain(self.osc2.iter_mut())
+            .chain(self.osc2_free.iter_mut())
+            .chain(self.osc1_octave.iter_mut())
+        {
+            osc.set_short_blep(eco);
+            osc.set_antialias(antialias);
+        }
+
+        self.tuning = 12.0 * (params.master_tuning.value() / 440.0).log2();
+        self.velocity_floor = params.velocity_floor.value();
+        self.min_gate_samples = (params.min_note_length.value() * self.sample_rate) as u32;
+        self.bend_range = (
+            params.bend_range_down.value() as f32,
+            params.bend_range_up.value() as f32,
+        );
 
         // These modulation depths should probably be smoothed at some point
         let osc1_lfo_pitch_mod_depth_semitones: f32 = params.lfo_osc1_detune_mod_depth.value();
+        let osc2_lfo_pitch_mod_depth_semitones: f32 = params.lfo_osc2_detune_mod_depth.value();
+        let pitch_mod_scale = if params.pitch_mod_quantize.value() {
+            Some(params.pitch_mod_scale.value())
+        } else {
+            None
+        };
+        let lfo_unison_detune_depth: f32 = params.lfo_unison_detune_depth.value();
+        // Harder notes play wider and softer ones narrower, as a change relative to the set
+        // amount like the LFO's
+        let velocity_unison_detune =
+            params.velocity_to_unison_detune.value() * velocity_offset(self.velocity);
         let filter_lfo_mod_depth: f32 = params.lfo_filter_mod_depth.value();
+        let filter_lfo_cos_mod_depth: f32 = params.lfo_cos_filter_mod_depth.value();
+        let pan_lfo_cos_depth: f32 = params.lfo_cos_pan_depth.value();
         let filter_velocity_mod_depth: f32 = params.filter_velocity_mod.value();
+        let filter_env_pitch_mod_semitones: f32 = params.filter_env_to_pitch.value();
+        let filter_env_pw_mod: f32 = params.filter_env_to_pw.value();
+        let accent: f32 = accent_amount(params.accent.value(), self.velocity);
+        let pw_keytrack: f32 = params.pw_keytrack.value();
+        let osc_sync: f64 = params.osc_sync.value() as f64;
+        let osc_spread_semitones: f32 = params.osc_spread.value() / 100.0;
+        let warmth_amount: f64 = params.warmth.value() as f64;
 
         let portamento: f32 = if params.poly_mode.value() {
             0.0
@@ -158,34 +450,87 @@ impl Voice {
         // Only update the envelopes if an envelope parameter has changed, and this particular voice has not updated since.
         let bit = 1u16 << (self.id as u16);
         if self.env_change.fetch_and(bit.not(), Ordering::Relaxed) & bit == bit {
+            self.amp_envelope
+                .set_oneshot(params.amp_env_oneshot.value());
+            let env_sync = params.env_sync.value();
+            let env_time = |seconds: &FloatParam, division: &EnumParam<NoteDivision>| {
+                if env_sync {
+                    division.value().seconds(tempo)
+                } else {
+                    seconds.value()
+                }
+            };
             self.amp_envelope.set_envelope_parameters(
                 self.sample_rate,
-                params.amp_env_attack.value(),
-                params.amp_env_decay.value(),
-                params.amp_env_sustain.value(),
-                params.amp_env_release.value(),
+                env_time(&params.amp_env_attack, &params.amp_env_attack_sync),
+                env_time(&params.amp_env_decay, &params.amp_env_decay_sync),
+                velocity_scale(
+                    params.amp_env_sustain.value(),
+                    self.velocity,
+                    params.velocity_to_sustain.value(),
+                ),
+                env_time(&params.amp_env_release, &params.amp_env_release_sync)
+                    .max(params.min_release.value()),
             );
             self.filter_envelope.set_envelope_parameters(
                 self.sample_rate,
-                params.filter_env_attack.value(),
-                params.filter_env_decay.value(),
+                env_time(&params.filter_env_attack, &params.filter_env_attack_sync),
+                env_time(&params.filter_env_decay, &params.filter_env_decay_sync),
                 params.filter_env_sustain.value(),
-                params.filter_env_release.value(),
+                env_time(&params.filter_env_release, &params.filter_env_release_sync),
             );
         }
 
         const KEYTRACK_PIVOT_NOTE: f64 = 48.0; // C3
 
-        let nvoices = self.unison;
+        // The unison count follows the parameter while the note sounds. Added voices fade in and
+        // removed ones fade out, keeping their detune and spread positions while they do.
+        self.unison = (params.unison_voices.value() as usize).clamp(1, MAX_UNISON);
+        let unison = self.unison;
+        for v in 0..unison {
+            self.unison_pattern[v] = (
+                UNISON_DETUNE_PATTERN[unison][v],
+                UNISON_SPREAD_PATTERN[unison][v],
+            );
+        }
+        let unison_fade_step = 1.0 / (UNISON_FADE_SECONDS * self.sample_rate);
+        let mut unison_fade = self.unison_fade.unwrap_or_else(|| {
+            let mut fade = [0.0; MAX_UNISON];
+            fade[..unison].fill(1.0);
+            fade
+        });
+        let nvoices = (unison..MAX_UNISON)
+            .rev()
+            .find(|v| unison_fade[*v] > 0.0)
+            .map_or(unison, |v| v + 1);
         let unison_scale = 1.0;
-        let detune_pattern = UNISON_DETUNE_PATTERN[nvoices];
-        let spread_pattern = UNISON_SPREAD_PATTERN[nvoices];
+        let detune_pattern = self.unison_pattern.map(|(detune, _)| detune);
+        let spread_pattern = self.unison_pattern.map(|(_, spread)| spread);
+        // Without unison (cheap stereo), or with unison summed into the mono filter, the width of
+        // the unison spread is put back after the filter by the widener. It starts from silence
+        // when switched in.
+        let widen = ((params.cheap_stereo.value() && nvoices == 1) || (mono_filter && nvoices > 1))
+            .then(|| params.unison_stereo_spread.value());
+        if widen.is_some() && !self.widener_on {
+            self.widener.clear();
+        }
+        self.widener_on = widen.is_some();
 
         let block_len = block_end - block_start;
 
-        // Audio-rate smoothed params into scratch arrays (Can't call next() per voice as they are shared between voices).
+        // Per sample decay so the start detune falls to 1% in START_DETUNE_SETTLE_SECONDS
+        let start_detune_coeff =
+            (0.01f32.ln() / (START_DETUNE_SETTLE_SECONDS * self.sample_rate)).exp();
+        // Same for the pitch envelope over its decay time
+        let pitch_env_coeff =
+            (0.01f32.ln() / (params.pitch_env_decay.value() * self.sample_rate)).exp();
+        let pitch_env_depth = params.pitch_env_depth.value() * self.note_amplitude() as f32;
+
+        // Audio-rate smoothed params into scratch arrays, read from the synth's smoothers without
+        // moving them along as every voice reads the same glides
         let mut params_filter_cutoff = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_filter_resonance = [0.0f32; MAX_BLOCK_SIZE];
+        let mut params_filter2_cutoff = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_osc1_pulsewidth = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_osc2_pulsewidth = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_osc1_detune = [0.0f32; MAX_BLOCK_SIZE];
@@ -193,83 +538,174 @@ impl Voice {
         let mut params_osc1_level = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_osc2_level = [0.0f32; MAX_BLOCK_SIZE];
         let mut params_master_gain = [0.0f32; MAX_BLOCK_SIZE];
-        params
+        param_smoothers
             .filter_cutoff
-            .smoothed
-            .next_block(&mut params_filter_cutoff, block_len);
-        params
+            .fill(&mut params_filter_cutoff[..block_len]);
+        param_smoothers
             .filter_resonance
-            .smoothed
-            .next_block(&mut params_filter_resonance, block_len);
-        params
+            .fill(&mut params_filter_resonance[..block_len]);
+        param_smoothers
+            .filter2_cutoff
+            .fill(&mut params_filter2_cutoff[..block_len]);
+        param_smoothers
             .osc1_pulsewidth
-            .smoothed
-            .next_block(&mut params_osc1_pulsewidth, block_len);
-        params
+            .fill(&mut params_osc1_pulsewidth[..block_len]);
+        param_smoothers
             .osc2_pulsewidth
-            .smoothed
-            .next_block(&mut params_osc2_pulsewidth, block_len);
-        params
+            .fill(&mut params_osc2_pulsewidth[..block_len]);
+        param_smoothers
             .osc1_level
-            .smoothed
-            .next_block(&mut params_osc1_level, block_len);
-        params
+            .fill(&mut params_osc1_level[..block_len]);
+        param_smoothers
             .osc2_level
-            .smoothed
-            .next_block(&mut params_osc2_level, block_len);
-        params
+            .fill(&mut params_osc2_level[..block_len]);
+        param_smoothers
             .osc1_detune
-            .smoothed
-            .next_block(&mut params_osc1_detune, block_len);
-        params
+            .fill(&mut params_osc1_detune[..block_len]);
+        param_smoothers
             .osc2_detune
-            .smoothed
-            .next_block(&mut params_osc2_detune, block_len);
-        params
+            .fill(&mut params_osc2_detune[..block_len]);
+        param_smoothers
             .master_gain
-            .smoothed
-            .next_block(&mut params_master_gain, block_len);
+            .fill(&mut params_master_gain[..block_len]);
+        // The controllers glide per sample too, or they step at every block boundary
+        let mut controller_values = [ControllerValues::default(); MAX_BLOCK_SIZE];
+        controllers.fill(&mut controller_values[..block_len]);
 
         for i in 0..block_len {
+            self.controllers = controller_values[i];
+            self.gate_samples = self.gate_samples.saturating_add(1);
+            if self.pending_note_off && self.gate_samples >= self.min_gate_samples {
+                self.pending_note_off = false;
+                self.note_off();
+            }
+            self.start_detune *= start_detune_coeff;
+            self.pitch_env *= pitch_env_coeff;
+            self.pitch_env_semitones = pitch_env_depth * self.pitch_env;
+
             let base_cutoff = params_filter_cutoff[i];
 
             // Do the filter key tracking in semitones
+            let key_semitones =
+                self.get_oscillator_semitone(0.0, portamento) - KEYTRACK_PIVOT_NOTE as f32;
             let base_cutoff_semitone: f32 = freq_to_midi_pitch_fast(base_cutoff as f32);
-            let cutoff_semitone = base_cutoff_semitone
-                + (self.get_oscillator_semitone(0.0, portamento) - KEYTRACK_PIVOT_NOTE as f32)
-                    * params.filter_key_track.value();
-
-            let lfo = self.lfo.generate(
-                lfo_waveform,
-                params.lfo_freq.value() as f64,
-                1.0,
-                0.5,
-                self.sample_rate,
-            ) as f32;
+            let key_track_semitones = key_semitones * params.filter_key_track.value();
+            let cutoff_semitone = base_cutoff_semitone + key_track_semitones + filter_drift;
 
-            let osc1_lfo_detune = osc1_lfo_pitch_mod_depth_semitones * lfo;
+            let lfo = self
+                .lfo
+                .generate(lfo_waveform, lfo_freq, 1.0, 0.5, self.sample_rate)
+                as f32;
+            // A quarter cycle ahead of the LFO, so the pair can trace a circle
+            let lfo_cos = self.lfo.quadrature(lfo_waveform) as f32;
+            // Glide from the previous LFO value for a moment after a retrigger instead of stepping
+            if self.lfo_fade_samples > 0 {
+                self.lfo_fade_samples -= 1;
+                self.lfo_value += (lfo - self.lfo_value) * lfo_fade_coeff;
+                self.lfo_cos_value += (lfo_cos - self.lfo_cos_value) * lfo_fade_coeff;
+            } else {
+                self.lfo_value = lfo;
+                self.lfo_cos_value = lfo_cos;
+            }
+            let lfo = self.lfo_value * velocity_lfo_scale;
+            let lfo_cos = self.lfo_cos_value * velocity_lfo_scale;
+
+            let amp_env = self.amp_envelope.next();
+            let filter_env = self.filter_envelope.next();
+
+            let mut osc1_lfo_detune = osc1_lfo_pitch_mod_depth_semitones * lfo;
+            let mut osc2_lfo_detune = osc2_lfo_pitch_mod_depth_semitones * lfo;
+            if let Some(scale) = pitch_mod_scale {
+                osc1_lfo_detune = scale.quantize(osc1_lfo_detune);
+                osc2_lfo_detune = scale.quantize(osc2_lfo_detune);
+            }
+            let filter_env_detune = filter_env_pitch_mod_semitones * filter_env;
 
-            let osc1_modulated_pw = params_osc1_pulsewidth[i];
-            let osc2_modulated_pw = params_osc2_pulsewidth[i];
+            let mut osc1_modulated_pw = params_osc1_pulsewidth[i];
+            let mut osc2_modulated_pw = params_osc2_pulsewidth[i];
+            if pw_keytrack != 0.0 || filter_env_pw_mod != 0.0 {
+                // Full key track = 0.5 pulse width change over 5 octaves
+                let pw_offset =
+                    pw_keytrack * key_semitones / 120.0 + filter_env_pw_mod * filter_env * 0.5;
+                osc1_modulated_pw = (osc1_modulated_pw + pw_offset).clamp(0.05, 0.95);
+                osc2_modulated_pw = (osc2_modulated_pw + pw_offset).clamp(0.05, 0.95);
+            }
             let amp = self.note_amplitude() as f32;
+            let osc_loudness = if osc_level_normalize {
+                (
+                    osc1_waveform.loudness_correction(osc1_modulated_pw),
+                    osc2_waveform.loudness_correction(osc2_modulated_pw),
+                )
+            } else {
+                (1.0, 1.0)
+            };
+
+            let osc1_detune =
+                params_osc1_detune[i] - osc_spread_semitones + osc1_lfo_detune + filter_env_detune;
 
-            let osc1_detune = params_osc1_detune[i] + osc1_lfo_detune;
+            // The per voice LFO breathes the unison spread in and out around the set amount, on top
+            // of the velocity offset
+            let unison_detune_amount = (params.unison_detune.value()
+                * (1.0 + lfo_unison_detune_depth * lfo + velocity_unison_detune))
+                .max(0.0);
+
+            osc_fade.0 = fade_towards(osc_fade.0, osc_on.0, osc_fade_step);
+            osc_fade.1 = fade_towards(osc_fade.1, osc_on.1, osc_fade_step);
+            let mut unison_gains = [0.0f32; MAX_UNISON];
+            for v in 0..nvoices {
+                unison_fade[v] = fade_towards(unison_fade[v], v < unison, unison_fade_step);
+                unison_gains[v] = self.unison_levels[v] * unison_fade[v];
+            }
+            let osc1_gain = osc_level_gain(params_osc1_level[i], osc_level_curve) * osc_fade.0;
+            let osc2_gain = osc_level_gain(params_osc2_level[i], osc_level_curve) * osc_fade.1;
 
             // Aggregate unison OSC1
             let mut osc1 = (0.0, 0.0);
+            let mut osc1_resets: [Option<f64>; MAX_UNISON] = [None; MAX_UNISON];
             for v in 0..nvoices {
+                if osc1_gain < OSC_SILENT_GAIN {
+                    // Skip the silent oscillator, but keep the glide at the same speed
+                    self.get_oscillator_semitone(0.0, portamento);
+                    continue;
+                }
+                let (unison_semitones, unison_hz) =
+                    unison_detune(detune_pattern[v], unison_detune_amount, unison_detune_mode);
                 let f1 = self.frequency(
-                    osc1_detune + detune_pattern[v] * params.unison_detune.value() + self.bend,
+                    osc1_detune + unison_semitones,
+                    unison_hz,
                     params.osc1_octave.value(),
                     portamento,
                 );
-                let mono_sample = self.osc1[v].generate(
+                let osc1_amplitude = (amp * osc1_gain * unison_gains[v]) as f64 * osc_loudness.0;
+                let mut mono_sample = self.osc1[v].generate(
                     osc1_waveform,
                     f1 as f64,
-                    (amp * params_osc1_level[i]) as f64,
+                    osc1_amplitude,
                     osc1_modulated_pw,
                     self.sample_rate,
                 );
+                osc1_resets[v] = self.osc1[v].reset_offset();
+
+                // The octave restarts with every OSC1 cycle, so the two never drift apart and beat
+                if osc1_octave_mix > 0.0 {
+                    let f_octave = 2.0 * f1;
+                    if let Some(offset) = osc1_resets[v] {
+                        self.osc1_octave[v].sync(
+                            osc1_waveform,
+                            offset,
+                            f_octave as f64,
+                            osc1_modulated_pw,
+                            self.sample_rate,
+                        );
+                    }
+                    mono_sample += self.osc1_octave[v].generate(
+                        osc1_waveform,
+                        f_octave as f64,
+                        osc1_amplitude * osc1_octave_mix as f64,
+                        osc1_modulated_pw,
+                        self.sample_rate,
+                    );
+                }
 
                 if nvoices == 1 {
                     osc1 = (osc1.0 + mono_sample, osc1.1 + mono_sample);
@@ -283,24 +719,71 @@ impl Voice {
                 }
             }
 
-            let osc2_detune = params_osc2_detune[i];
+            let osc2_note_offset = self
+                .osc2_note
+                .map_or(0.0, |n| n as f32 - self.target_note as f32);
+            let osc2_detune = params_osc2_detune[i]
+                + osc_spread_semitones
+                + osc2_lfo_detune
+                + filter_env_detune
+                + osc2_note_offset;
 
             // Aggregate unison OSC2
             let mut osc2 = (0.0f64, 0.0f64);
 
             for v in 0..nvoices {
+                if osc2_gain < OSC_SILENT_GAIN {
+                    self.get_oscillator_semitone(0.0, portamento);
+                    continue;
+                }
+                let (unison_semitones, unison_hz) =
+                    unison_detune(detune_pattern[v], unison_detune_amount, unison_detune_mode);
                 let f2 = self.frequency(
-                    osc2_detune + detune_pattern[v] * params.unison_detune.value() + self.bend,
+                    osc2_detune + unison_semitones,
+                    unison_hz,
                     params.osc2_octave.value(),
                     portamento,
                 );
-                let mono_sample = self.osc2[v].generate(
-                    osc2_waveform,
-                    f2 as f64,
-                    (amp * params_osc2_level[i]) as f64,
-                    osc2_modulated_pw,
-                    self.sample_rate,
-                );
+                let osc2_amp = (amp * osc2_gain * unison_gains[v]) as f64 * osc_loudness.1;
+                let mono_sample = if osc_sync > 0.0 {
+                    // Hard sync per unison voice pair: OSC2 voice v restarts with OSC1 voice v.
+                    if let Some(offset) = osc1_resets[v] {
+                        self.osc2[v].sync(
+                            osc2_waveform,
+                            offset,
+                            f2 as f64,
+                            osc2_modulated_pw,
+                            self.sample_rate,
+                        );
+                    }
+                    let synced = self.osc2[v].generate(
+                        osc2_waveform,
+                        f2 as f64,
+                        osc2_amp,
+                        osc2_modulated_pw,
+                        self.sample_rate,
+                    );
+                    if osc_sync < 1.0 {
+                        let free = self.osc2_free[v].generate(
+                            osc2_waveform,
+                            f2 as f64,
+                            osc2_amp,
+                            osc2_modulated_pw,
+                            self.sample_rate,
+                        );
+                        synced * osc_sync + free * (1.0 - osc_sync)
+                    } else {
+                        synced
+                    }
+                } else {
+                    self.osc2[v].generate(
+                        osc2_waveform,
+                        f2 as f64,
+                        osc2_amp,
+                        osc2_modulated_pw,
+                        self.sample_rate,
+                    )
+                };
 
                 if nvoices == 1 {
                     osc2 = (osc2.0 + mono_sample, osc2.1 + mono_sample);
@@ -316,45 +799,384 @@ impl Voice {
 
             osc1 = (osc1.0 * unison_scale, osc1.1 * unison_scale);
             osc2 = (osc2.0 * unison_scale, osc2.1 * unison_scale);
+            self.osc_peak = (
+                self.osc_peak
+                    .0
+                    .max(osc1.0.abs().max(osc1.1.abs()) as f32 * amp_env),
+                self.osc_peak
+                    .1
+                    .max(osc2.0.abs().max(osc2.1.abs()) as f32 * amp_env),
+            );
 
-            let amp_env = self.amp_envelope.next();
-            let filter_env = self.filter_envelope.next();
             let filter_env_mod_depth = params.filter_env_mod_gain.value();
 
-            let sample = (osc1.0 + osc2.0, osc1.1 + osc2.1);
+            // Bypassed oscillators skip the filter and join the signal after it
+            let mut sample = (0.0, 0.0);
+            let mut dry = (0.0f32, 0.0f32);
+            for (osc, bypass) in [(osc1, filter_bypass.0), (osc2, filter_bypass.1)] {
+                if bypass {
+                    dry = (dry.0 + osc.0 as f32, dry.1 + osc.1 as f32);
+                } else {
+                    sample = (sample.0 + osc.0, sample.1 + osc.1);
+                }
+            }
+            if warmth_amount > 0.0 {
+                sample = (
+                    self.warmth_dc.0.process(warmth(sample.0, warmth_amount)),
+                    self.warmth_dc.1.process(warmth(sample.1, warmth_amount)),
+                );
+            }
+            if mono_filter {
+                sample.0 = (sample.0 + sample.1) * 0.5;
+            }
 
-            // Modulate cutoff in semitones
-            let cutoff_mod_semitones = (filter_env * filter_env_mod_depth
-                + lfo * filter_lfo_mod_depth
-                + amp * filter_velocity_mod_depth)
-                * 10.0
-                * 12.0; // Full mod = 10 octaves = 120st
+            let cutoff_mod_semitones = self.frozen_cutoff_mod.unwrap_or_else(|| {
+                cutoff_modulation_semitones(
+                    filter_env * filter_env_mod_depth
+                        + lfo * filter_lfo_mod_depth
+                        + lfo_cos * filter_lfo_cos_mod_depth
+                        + amp * filter_velocity_mod_depth,
+                )
+            });
+            if filter_freeze {
+                self.frozen_cutoff_mod = Some(cutoff_mod_semitones);
+            }
 
-            let modulated_cutoff =
-                midi_pitch_to_freq(cutoff_semitone + cutoff_mod_semitones).clamp(20.0, 20000.0);
+            let modulated_cutoff = midi_pitch_to_freq(cutoff_semitone + cutoff_mod_semitones)
+                .clamp(cutoff_floor, 20000.0);
+            self.cutoff = modulated_cutoff;
 
             let master = params_master_gain[i];
 
-            let resonance = params_filter_resonance[i];
-            let filtered_sample_l = self.filter.0.process(
+            // Accent spikes the resonance with the filter envelope attack, settling with the decay
+            let resonance = (params_filter_resonance[i] + accent * filter_env).min(1.0);
+            let mut filtered_sample_l = self.filter.0.process(
                 sample.0 as f32,
                 self.sample_rate,
                 modulated_cutoff,
                 resonance,
             );
-            let filtered_sample_r = self.filter.1.process(
-                sample.1 as f32,
-                self.sample_rate,
-                modulated_cutoff,
-                resonance,
+            let mut filtered_sample_r = 0.0;
+            if !mono_filter {
+                filtered_sample_r = self.filter.1.process(
+                    sample.1 as f32,
+                    self.sample_rate,
+                    modulated_cutoff,
+                    resonance,
+                );
+            }
+            if filter_routing == FilterRouting::Series {
+                filtered_sample_l = self.filter_series.0.process(
+                    filtered_sample_l,
+                    self.sample_rate,
+                    modulated_cutoff,
+                    resonance,
+                );
+                if !mono_filter {
+                    filtered_sample_r = self.filter_series.1.process(
+                        filtered_sample_r,
+                        self.sample_rate,
+                        modulated_cutoff,
+                        resonance,
+                    );
+                }
+            }
+            if filter2_mode.is_some() {
+                let cutoff2 = midi_pitch_to_freq(
+                    freq_to_midi_pitch_fast(params_filter2_cutoff[i])
+                        + key_track_semitones
+                        + cutoff_mod_semitones,
+                )
+                .clamp(cutoff_floor, 20000.0);
+                let filtered2_l =
+                    self.filter2
+                        .0
+                        .process(sample.0 as f32, self.sample_rate, cutoff2, resonance);
+                filtered_sample_l += (filtered2_l - filtered_sample_l) * filter_blend;
+                if !mono_filter {
+                    let filtered2_r = self.filter2.1.process(
+                        sample.1 as f32,
+                        self.sample_rate,
+                        cutoff2,
+                        resonance,
+                    );
+                    filtered_sample_r += (filtered2_r - filtered_sample_r) * filter_blend;
+                }
+            }
+            if mono_filter {
+                filtered_sample_r = filtered_sample_l;
+            }
+            filtered_sample_l += dry.0;
+            filtered_sample_r += dry.1;
+            let mut amp_sample = (filtered_sample_l * amp_env, filtered_sample_r * amp_env);
+            if let Some(widen) = widen {
+                amp_sample = self.widener.process(amp_sample.0, amp_sample.1, widen);
+            }
+            if voice_softclip {
+                amp_sample = (amp_sample.0.tanh(), amp_sample.1.tanh());
+            }
+            let mut amp_sample = (amp_sample.0 * master, amp_sample.1 * master);
+            let pan = if pan_lfo_cos_depth != 0.0 {
+                let position = (self.pan + lfo_cos * pan_lfo_cos_depth).clamp(-1.0, 1.0);
+                Some(crate::pan_gains(position))
+            } else {
+                pan
+            };
+            if let Some((left, right)) = pan {
+                amp_sample = (amp_sample.0 * left, amp_sample.1 * right);
+            }
+
+            add_to_output(output, block_start + i, amp_sample);
+        }
+        self.osc_fade = Some(osc_fade);
+        self.unison_fade = Some(unison_fade);
+    }
+}
+
+/// Gentle asymmetric soft clip adding mostly even harmonics. Transparent at amount 0.
+fn warmth(x: f64, amount: f64) -> f64 {
+    const BIAS: f64 = 0.3;
+    let drive = 1.0 + 2.0 * amount;
+    let shaped = ((x * drive + BIAS).tanh() - BIAS.tanh()) / drive;
+    x + (shaped - x) * amount
+}
+
+#[derive(Default)]
+struct DcBlocker {
+    x1: f64,
+    y1: f64,
+}
+
+impl DcBlocker {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = x - self.x1 + 0.995 * self.y1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Unison voice detune as (semitones, Hz). Musical mode spreads by a fixed interval, Hz mode by
+/// a fixed frequency so the beating rate is the same across the keyboard.
+fn unison_detune(pattern: f32, amount: f32, mode: UnisonDetuneMode) -> (f32, f32) {
+    match mode {
+        UnisonDetuneMode::Musical => (pattern * amount, 0.0),
+        UnisonDetuneMode::Hz => (0.0, pattern * amount * UNISON_DETUNE_MAX_HZ),
+    }
+}
+
+/// Move an on/off fade one step towards fully on or off.
+fn fade_towards(fade: f32, on: bool, step: f32) -> f32 {
+    if on {
+        (fade + step).min(1.0)
+    } else {
+        (fade - step).max(0.0)
+    }
+}
+
+/// Mix a stereo sample into the output, summed to mono when there is only one channel.
+pub(crate) fn add_to_output(output: &mut [&mut [f32]], index: usize, sample: (f32, f32)) {
+    match output {
+        [mono] => mono[index] += (sample.0 + sample.1) * 0.5,
+        [left, right, ..] => {
+            left[index] += sample.0;
+            right[index] += sample.1;
+        }
+        [] => (),
+    }
+}
+
+/// Amplitude for an oscillator level knob at `level` (0..1)
+fn osc_level_gain(level: f32, curve: OscLevelCurve) -> f32 {
+    match curve {
+        OscLevelCurve::Linear => level,
+        OscLevelCurve::Decibel => {
+            if level < OSC_SILENT_GAIN {
+                0.0
+            } else {
+                10f32.powf(3.0 * (level - 1.0)) // -60 dB..0 dB
+            }
+        }
+    }
+}
+
+/// Scale `value` by velocity. Full velocity keeps the value, softer notes lower it by `amount`.
+fn velocity_scale(value: f32, velocity: u8, amount: f32) -> f32 {
+    value * (1.0 - amount * (1.0 - midi_velocity_to_amplitude(velocity)))
+}
+
+/// Accent grows with velocity above ACCENT_VELOCITY, so harder notes scream more
+fn accent_amount(accent: f32, velocity: u8) -> f32 {
+    let over = velocity.saturating_sub(ACCENT_VELOCITY) as f32;
+    accent * over / (127 - ACCENT_VELOCITY) as f32
+}
+
+/// Velocity as -1..1, 0 in the middle of the range
+fn velocity_offset(velocity: u8) -> f32 {
+    ((velocity as f32 - 64.0) / 63.0).clamp(-1.0, 1.0)
+}
+
+// Modulate cutoff in semitones. Full mod = 10 octaves = 120st
+fn cutoff_modulation_semitones(modulation: f32) -> f32 {
+    modulation * 10.0 * 12.0
+}
+
+#[allow(unused)]
+mod tests {
+    use super::{
+        accent_amount, add_to_output, velocity_offset, Voice, ACCENT_VELOCITY, MAX_UNISON,
+    };
+    use crate::controllers::Controllers;
+    use crate::param_smoothing::ParamSmoothers;
+    use crate::{freq_param, SynthParams};
+    use assert_approx_eq::assert_approx_eq;
+    use nih_plug::prelude::*;
+    use std::sync::atomic::AtomicU16;
+    use std::sync::Arc;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    // Default parameters changed by `configure`
+    fn test_params(configure: impl FnOnce(&mut SynthParams)) -> (Arc<SynthParams>, Arc<AtomicU16>) {
+        let env_chg = Arc::new(AtomicU16::new(u16::MAX));
+        let mut params = SynthParams::new(env_chg.clone());
+        configure(&mut params);
+        (Arc::new(params), env_chg)
+    }
+
+    fn play(voice: &mut Voice, params: &SynthParams, note: u8) {
+        let unison = params.unison_voices.value() as usize;
+        voice.note_on(
+            note,
+            127,
+            0.0,
+            unison,
+            true,
+            &[0.0; MAX_UNISON],
+            &[1.0; MAX_UNISON],
+            0.0,
+        );
+    }
+
+    // Stereo output of one voice for `samples` samples, in 64 sample blocks, with the block
+    // smoothers starting at the parameter values
+    fn render(voice: &mut Voice, params: &mut Arc<SynthParams>, samples: usize) -> [Vec<f32>; 2] {
+        let (mut left, mut right) = (vec![0.0; samples], vec![0.0; samples]);
+        let controllers = Controllers::default();
+        let mut param_smoothers = ParamSmoothers::default();
+        param_smoothers.reset(params);
+        for block_start in (0..samples).step_by(64) {
+            let block_end = (block_start + 64).min(samples);
+            voice.generate(
+                params,
+                &controllers,
+                &param_smoothers,
+                &mut [&mut left[..], &mut right[..]],
+                block_start,
+                block_end,
+                120.0,
+                None,
             );
-            let amp_sample = (
-                filtered_sample_l * amp_env * master,
-                filtered_sample_r * amp_env * master,
+        }
+        [left, right]
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    // Cutoff of a voice every 16 samples through the filter envelope, with env mod `depth`
+    fn filter_env_sweep(depth: f32) -> Vec<f32> {
+        let (mut params, env_chg) = test_params(|params| {
+            params.filter_cutoff = freq_param("Filter Cutoff", 1000.0);
+            params.filter_env_mod_gain = FloatParam::new(
+                "Filter env mod",
+                depth,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
             );
+        });
+        let mut voice = Voice::new(0, SAMPLE_RATE, &env_chg);
+        play(&mut voice, &params, 60);
+        (0..256)
+            .map(|_| {
+                render(&mut voice, &mut params, 16);
+                voice.cutoff
+            })
+            .collect()
+    }
+
+    #[test]
+    fn negative_filter_env_mod_sweeps_down_as_far_as_positive_sweeps_up() {
+        let base = filter_env_sweep(0.0);
+        let up = filter_env_sweep(0.25);
+        let down = filter_env_sweep(-0.25);
+        assert!(up.iter().zip(&base).any(|(up, base)| *up > 2.0 * base));
+        for ((base, up), down) in base.iter().zip(&up).zip(&down) {
+            // Equal ratio up and down in Hz, so equal semitones
+            assert_approx_eq!((up / base).log2(), (base / down).log2(), 1e-3);
+        }
+    }
 
-            output[0][block_start + i] += amp_sample.0;
-            output[1][block_start + i] += amp_sample.1;
+    // The mono filter gives one filtered signal, but pan and the unison width still apply to it
+    #[test]
+    fn mono_filter_keeps_pan_and_unison_width() {
+        let (mut params, env_chg) = test_params(|params| {
+            params.mono_filter = BoolParam::new("Mono Filter", true);
+            params.unison_voices =
+                IntParam::new("Unison Voices", 3, IntRange::Linear { min: 1, max: 7 });
+        });
+
+        let mut voice = Voice::new(0, SAMPLE_RATE, &env_chg);
+        voice.pan = 0.5;
+        play(&mut voice, &params, 48);
+        let [left, right] = render(&mut voice, &mut params, 8192);
+        assert!(rms(&right[4096..]) > 1.5 * rms(&left[4096..]));
+
+        let mut voice = Voice::new(0, SAMPLE_RATE, &env_chg);
+        play(&mut voice, &params, 48);
+        let [left, right] = render(&mut voice, &mut params, 8192);
+        let difference: Vec<f32> = left.iter().zip(&right).map(|(l, r)| l - r).collect();
+        assert!(rms(&difference[4096..]) > 0.1 * rms(&left[4096..]));
+    }
+
+    #[test]
+    fn accent_grows_with_velocity_above_the_threshold() {
+        assert_eq!(accent_amount(0.8, 40), 0.0);
+        assert_eq!(accent_amount(0.8, ACCENT_VELOCITY), 0.0);
+        let soft = accent_amount(0.8, ACCENT_VELOCITY + 10);
+        let hard = accent_amount(0.8, 120);
+        assert!(0.0 < soft && soft < hard && hard < 0.8);
+        assert_approx_eq!(accent_amount(0.8, 127), 0.8);
+    }
+
+    // Velocity modulation centred on the middle velocity goes both ways
+    #[test]
+    fn velocity_offset_is_centred() {
+        assert_eq!(velocity_offset(64), 0.0);
+        assert_eq!(velocity_offset(127), 1.0);
+        assert_eq!(velocity_offset(1), -1.0);
+        assert_eq!(velocity_offset(0), -1.0);
+    }
+
+    #[test]
+    fn mono_output_gets_the_sum_of_both_channels() {
+        let (mut left, mut right, mut mono) = ([0.0f32; 4], [0.0f32; 4], [0.0f32; 4]);
+        for (i, sample) in [(0.5, 0.5), (1.0, -1.0), (0.25, 0.75), (0.0, 0.1)]
+            .into_iter()
+            .enumerate()
+        {
+            add_to_output(&mut [&mut left[..], &mut right[..]], i, sample);
+            add_to_output(&mut [&mut mono[..]], i, sample);
+            // Adding more voices keeps mixing into the same sample
+            add_to_output(&mut [&mut mono[..]], i, sample);
+        }
+        assert_eq!(left, [0.5, 1.0, 0.25, 0.0]);
+        assert_eq!(right, [0.5, -1.0, 0.75, 0.1]);
+        for i in 0..4 {
+            assert_approx_eq!(mono[i], left[i] + right[i]);
         }
     }
 }
@@ -0,0 +1,69 @@
+// Phase based stereo widener. A pair of allpass chains keeps a near constant 90 degree phase
+// difference over most of the audio band (coefficients from Olli Niemitalo's Hilbert transformer),
+// so right can be rotated away from left in phase without changing the level of either. Because
+// the difference is the same at every frequency, the mono sum loses a little level evenly instead
+// of comb filtering like a Haas delay.
+
+const PATH_A: [f32; 4] = [0.6923878, 0.9360654, 0.9882295, 0.9987488];
+const PATH_B: [f32; 4] = [0.4021921, 0.8561711, 0.972291, 0.9952885];
+
+/// Second order allpass in z^2, y[n] = a^2 (x[n] + y[n-2]) - x[n-2]
+#[derive(Default, Clone, Copy)]
+struct Allpass {
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32, coefficient: f32) -> f32 {
+        let output = coefficient * coefficient * (input + self.y[1]) - self.x[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+#[derive(Default)]
+struct HilbertPair {
+    a: [Allpass; 4],
+    b: [Allpass; 4],
+    b_delay: f32, // Path B is one sample ahead of path A
+}
+
+impl HilbertPair {
+    /// (in phase, quadrature) outputs, 90 degrees apart
+    fn process(&mut self, input: f32) -> (f32, f32) {
+        let a = self
+            .a
+            .iter_mut()
+            .zip(PATH_A)
+            .fold(input, |x, (allpass, c)| allpass.process(x, c));
+        let b = self
+            .b
+            .iter_mut()
+            .zip(PATH_B)
+            .fold(input, |x, (allpass, c)| allpass.process(x, c));
+        let delayed_b = self.b_delay;
+        self.b_delay = b;
+        (a, delayed_b)
+    }
+}
+
+#[derive(Default)]
+pub struct Widener {
+    channels: (HilbertPair, HilbertPair),
+}
+
+impl Widener {
+    /// Rotate the phase of the right channel against the left, by up to 90 degrees at amount 1.
+    pub fn process(&mut self, left: f32, right: f32, amount: f32) -> (f32, f32) {
+        let angle = amount.clamp(0.0, 1.0) * std::f32::consts::FRAC_PI_2;
+        let (left, _) = self.channels.0.process(left);
+        let (right, right_quadrature) = self.channels.1.process(right);
+        (left, right * angle.cos() + right_quadrature * angle.sin())
+    }
+
+    pub fn clear(&mut self) {
+        self.channels = Default::default();
+    }
+}